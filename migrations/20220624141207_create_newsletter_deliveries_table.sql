@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE newsletter_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    provider_response TEXT NULL,
+    n_attempts SMALLINT NOT NULL,
+    first_attempted_at timestamptz NOT NULL,
+    last_attempted_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+ALTER TABLE newsletter_issues ADD COLUMN publish_at timestamptz NOT NULL DEFAULT now();
+UPDATE newsletter_issues SET publish_at = published_at;
+ALTER TABLE newsletter_issues ADD COLUMN cancelled_at timestamptz NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+-- Drafts have not been published yet.
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE api_tokens (
+    token_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    name TEXT NOT NULL,
+    -- SHA-256 of the token, hex-encoded. The token itself is never stored.
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL,
+    PRIMARY KEY (token_id)
+);
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE subsciption_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours',
+    ADD COLUMN used_at timestamptz NULL;
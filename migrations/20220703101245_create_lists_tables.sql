@@ -0,0 +1,39 @@
+-- Add migration script here
+CREATE TABLE lists (
+    list_id uuid PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE list_subscriptions (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+-- Everything published so far went to the single global list.
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT l.list_id, s.id, s.status, s.subscribed_at
+FROM subscriptions s, lists l
+WHERE l.slug = 'newsletter';
+
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT i.newsletter_issue_id, l.list_id
+FROM newsletter_issues i, lists l
+WHERE l.slug = 'newsletter';
+
+ALTER TABLE subsciption_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subsciption_tokens SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subsciption_tokens ALTER COLUMN list_id SET NOT NULL;
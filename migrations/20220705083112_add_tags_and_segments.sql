@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+
+CREATE TABLE subscriber_attributes (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, name)
+);
+
+-- The segment filter an issue was sent with, NULL when it went to every
+-- subscriber of its lists.
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
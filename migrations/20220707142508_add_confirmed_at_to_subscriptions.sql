@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+
+-- The confirmation date was not recorded so far, the subscription date is the
+-- closest we have.
+UPDATE subscriptions SET confirmed_at = subscribed_at WHERE status = 'confirmed';
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The Markdown source of issues written in Markdown, from which the HTML and
+-- plain text contents were generated.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
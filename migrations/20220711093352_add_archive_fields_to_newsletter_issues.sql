@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+-- Private issues are left out of the public archive and feed.
+ALTER TABLE newsletter_issues ADD COLUMN private BOOLEAN NOT NULL DEFAULT false;
+
+-- Drafts get their slug when they are published.
+UPDATE newsletter_issues
+SET slug = coalesce(
+        nullif(trim(both '-' from lower(regexp_replace(title, '[^a-zA-Z0-9]+', '-', 'g'))), ''),
+        'issue'
+    ) || '-' || left(newsletter_issue_id::text, 8)
+WHERE status = 'published';
@@ -0,0 +1,17 @@
+-- Add migration script here
+-- Opens and clicks are only recorded for issues with tracking turned on.
+ALTER TABLE newsletter_issues ADD COLUMN tracking BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE issue_events (
+    event_id uuid NOT NULL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    -- 'open' or 'click'
+    kind TEXT NOT NULL,
+    -- The destination of a click.
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX issue_events_newsletter_issue_id_idx ON issue_events (newsletter_issue_id);
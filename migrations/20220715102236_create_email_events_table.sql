@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Bounces, spam complaints and deliveries reported by the email provider.
+CREATE TABLE email_events (
+    event_id uuid NOT NULL PRIMARY KEY,
+    -- 'Bounce', 'SpamComplaint' or 'Delivery'
+    record_type TEXT NOT NULL,
+    email TEXT NOT NULL,
+    message_id TEXT NULL,
+    -- The bounce type, e.g. 'HardBounce'.
+    details TEXT NULL,
+    -- The webhook body, as received.
+    payload TEXT NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_email_idx ON email_events (lower(email));
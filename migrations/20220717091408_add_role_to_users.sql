@@ -0,0 +1,4 @@
+-- Add migration script here
+-- 'owner', 'editor' or 'viewer'. Existing users keep full access.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner';
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN disabled_at timestamptz NULL;
+
+CREATE TABLE user_invites(
+    invite_id uuid NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    invited_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    PRIMARY KEY(invite_id)
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT false;
+
+-- The seeded admin's password is public: it has to be changed on first login.
+UPDATE users SET must_change_password = true
+WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+    AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8';
+
+-- Hash of the token of the first-run setup page, while there is no user.
+CREATE TABLE setup_tokens(
+    token_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(token_hash)
+);
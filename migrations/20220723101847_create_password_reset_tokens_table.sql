@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Bumped to end all the sessions of a user.
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE password_reset_tokens(
+    -- SHA-256 of the token, hex-encoded. The token itself is never stored.
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(token_hash)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- The secret is encrypted: the nonce followed by the ciphertext.
+-- It is set while enrolling, and only required at login once enabled.
+ALTER TABLE users ADD COLUMN totp_secret BYTEA;
+ALTER TABLE users ADD COLUMN totp_enabled_at timestamptz;
+-- The last time step a code was accepted for, so that codes cannot be replayed.
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
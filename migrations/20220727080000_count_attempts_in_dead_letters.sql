@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Dead letters used to record the retries made before the last, failed attempt.
+ALTER TABLE issue_delivery_dead_letters RENAME COLUMN n_retries TO n_attempts;
+UPDATE issue_delivery_dead_letters SET n_attempts = n_attempts + 1;
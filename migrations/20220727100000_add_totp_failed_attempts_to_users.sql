@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Wrong second factors since the last right one. They are kept here rather
+-- than in the session, so that entering the password again does not reset them.
+ALTER TABLE users ADD COLUMN totp_failed_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_last_failed_at timestamptz;
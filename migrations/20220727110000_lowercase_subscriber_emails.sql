@@ -0,0 +1,41 @@
+-- Add migration script here
+-- Addresses are now stored lowercased, as `SubscriberEmail::parse` returns
+-- them. An address is left as it is when lowercasing it would clash with
+-- another subscriber.
+CREATE TEMPORARY TABLE renamed_emails AS
+SELECT DISTINCT ON (lower(email)) email AS old_email, lower(email) AS new_email
+FROM subscriptions s
+WHERE email <> lower(email)
+    AND NOT EXISTS (SELECT 1 FROM subscriptions o WHERE o.email = lower(s.email))
+ORDER BY lower(email), subscribed_at;
+
+UPDATE subscriptions s SET email = r.new_email
+FROM renamed_emails r WHERE s.email = r.old_email;
+
+UPDATE issue_delivery_queue q SET subscriber_email = r.new_email
+FROM renamed_emails r
+WHERE q.subscriber_email = r.old_email
+    AND NOT EXISTS (
+        SELECT 1 FROM issue_delivery_queue o
+        WHERE o.newsletter_issue_id = q.newsletter_issue_id AND o.subscriber_email = r.new_email
+    );
+
+UPDATE newsletter_deliveries d SET subscriber_email = r.new_email
+FROM renamed_emails r
+WHERE d.subscriber_email = r.old_email
+    AND NOT EXISTS (
+        SELECT 1 FROM newsletter_deliveries o
+        WHERE o.newsletter_issue_id = d.newsletter_issue_id AND o.subscriber_email = r.new_email
+    );
+
+UPDATE issue_delivery_dead_letters d SET subscriber_email = r.new_email
+FROM renamed_emails r
+WHERE d.subscriber_email = r.old_email
+    AND NOT EXISTS (
+        SELECT 1 FROM issue_delivery_dead_letters o
+        WHERE o.newsletter_issue_id = d.newsletter_issue_id AND o.subscriber_email = r.new_email
+    );
+
+UPDATE email_events SET email = lower(email) WHERE email <> lower(email);
+
+DROP TABLE renamed_emails;
@@ -0,0 +1,104 @@
+use super::{AuthError, Role};
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// Generate a new API token for a user.
+///
+/// Only a hash of the token is stored: the returned value is the one and only
+/// chance to show it to the user.
+#[tracing::instrument(name = "Create an API token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let random_bytes: [u8; 32] = rand::thread_rng().gen();
+    let token = format!("{}{}", TOKEN_PREFIX, hex::encode(random_bytes));
+
+    sqlx::query!(
+        r#"INSERT INTO api_tokens (token_id, user_id, name, token_hash, created_at) VALUES ($1, $2, $3, $4, now())"#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API token.")?;
+
+    Ok(Secret::new(token))
+}
+
+/// Returns the id and the role of the user owning the token, recording that
+/// it was used. The tokens of disabled users are rejected.
+#[tracing::instrument(name = "Validate API token", skip(token, pool))]
+pub async fn validate_api_token(
+    token: Secret<String>,
+    pool: &PgPool,
+) -> Result<(Uuid, Role), AuthError> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t SET last_used_at = now()
+        FROM users u
+        WHERE t.token_hash = $1 AND t.revoked_at IS NULL
+            AND u.user_id = t.user_id AND u.disabled_at IS NULL
+        RETURNING t.user_id, u.role
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Unable to perform a query to validate an API token.")?;
+
+    let row = row
+        .ok_or_else(|| anyhow::anyhow!("Unknown or revoked API token."))
+        .map_err(AuthError::InvalidCredentials)?;
+    let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+    Ok((row.user_id, role))
+}
+
+/// Returns `false` if the user has no active token with the given id.
+#[tracing::instrument(name = "Revoke an API token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_revoked = sqlx::query!(
+        r#"UPDATE api_tokens SET revoked_at = now() WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API token.")?
+    .rows_affected();
+
+    Ok(n_revoked > 0)
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Result<Secret<String>, anyhow::Error> {
+    let auth_header = headers
+        .get("Authorization")
+        .context("missing Authorization header")?
+        .to_str()
+        .context("Authorization header was not properly utf-8 encoded")?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .context("The authorization scheme was not 'Bearer '.")?;
+
+    Ok(Secret::new(token.trim().to_owned()))
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -1,11 +1,16 @@
+use super::Role;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
+use actix_web::web;
 use actix_web::FromRequest;
 use actix_web::HttpMessage;
+use actix_web_flash_messages::FlashMessage;
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -25,23 +30,118 @@ impl Deref for UserID {
     }
 }
 
+/// The pages a user who must change their password can still reach.
+const PASSWORD_ROTATION_PATHS: &[&str] = &["/admin/password", "/admin/logout"];
+
+struct SessionUser {
+    role: Role,
+    must_change_password: bool,
+    session_version: i32,
+}
+
+/// Also makes the [`Role`](super::Role) of the user available to the
+/// handlers. Sessions of users that have been deleted or disabled, or that
+/// were ended by [`end_all_sessions`], are logged out. Users who must change
+/// their password are sent to do it first.
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
-    next: Next<impl MessageBody>,
+    next: Next<impl MessageBody + 'static>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserID(user_id));
-            next.call(req).await
-        }
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
         None => {
             let resp = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
+            return Err(InternalError::from_response(e, resp).into());
+        }
+    };
+    let session_version = session.get_session_version().map_err(e500)?;
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The database pool is not registered.");
+    match get_session_user(pool, user_id).await.map_err(e500)? {
+        Some(user) if Some(user.session_version) == session_version => {
+            if user.must_change_password && !PASSWORD_ROTATION_PATHS.contains(&req.path()) {
+                // Not an error response: those would drop the flash message.
+                FlashMessage::error("You must change your password before going further.").send();
+                return Ok(req
+                    .into_response(see_other("/admin/password"))
+                    .map_into_right_body());
+            }
+            req.extensions_mut().insert(UserID(user_id));
+            req.extensions_mut().insert(user.role);
+            next.call(req).await.map(|r| r.map_into_left_body())
+        }
+        _ => {
+            session.log_out();
+            let resp = see_other("/login");
+            let e = anyhow::anyhow!("The user no longer exists, is disabled or was logged out");
             Err(InternalError::from_response(e, resp).into())
         }
     }
 }
+
+/// `None` if the user does not exist or has been disabled.
+#[tracing::instrument(name = "Get the session user", skip(pool))]
+async fn get_session_user(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<SessionUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role, must_change_password, session_version
+        FROM users
+        WHERE user_id = $1 AND disabled_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the session user.")?;
+
+    row.map(|r| {
+        Ok(SessionUser {
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+            must_change_password: r.must_change_password,
+            session_version: r.session_version,
+        })
+    })
+    .transpose()
+}
+
+/// Stored in the session on login: sessions holding an older version are
+/// logged out.
+#[tracing::instrument(name = "Get the session version of a user", skip(pool))]
+pub async fn get_session_version(pool: &PgPool, user_id: Uuid) -> Result<i32, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT session_version FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the session version of a user.")?;
+
+    Ok(row.session_version)
+}
+
+/// Log the user out everywhere.
+#[tracing::instrument(name = "End all the sessions of a user", skip(executor))]
+pub async fn end_all_sessions<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET session_version = session_version + 1 WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to end the sessions of a user.")?;
+
+    Ok(())
+}
@@ -1,6 +1,19 @@
+mod api_token;
 mod middleware;
 mod password;
+mod roles;
+mod totp;
 
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub(crate) use api_token::hash_token;
+pub use api_token::{bearer_token, create_api_token, revoke_api_token, validate_api_token};
+pub use password::{
+    change_password, create_user, must_change_password, validate_credentials, AuthError,
+    Credentials,
+};
 
-pub use middleware::{reject_anonymous_users, UserID};
+pub use middleware::{end_all_sessions, get_session_version, reject_anonymous_users, UserID};
+pub use roles::{Editors, MinimumRole, Owners, RequireRole, Role};
+pub use totp::{
+    begin_totp_enrolment, current_totp_step, disable_totp, enable_totp, get_totp_status, has_totp,
+    provisioning_uri, totp_code, verify_second_factor, SecondFactor, TotpKey, TotpStatus,
+};
@@ -1,9 +1,10 @@
+use super::Role;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
@@ -32,11 +33,14 @@ pub async fn validate_credentials(
             .to_string(),
     );
 
-    if let Some((stored_user_id, stored_password_hash)) =
+    let mut disabled = false;
+
+    if let Some((stored_user_id, stored_password_hash, stored_disabled)) =
         get_stored_credentials(&credentials.username, &pool).await?
     {
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
+        disabled = stored_disabled;
     }
 
     spawn_blocking_with_tracing(move || {
@@ -45,6 +49,12 @@ pub async fn validate_credentials(
     .await
     .context("Unable to spawn bloking task.br")??;
 
+    if disabled {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "The account is disabled."
+        )));
+    }
+
     user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
         .map_err(AuthError::InvalidCredentials)
@@ -54,15 +64,15 @@ pub async fn validate_credentials(
 async fn get_stored_credentials(
     username: &str,
     pool: &PgPool,
-) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+) -> Result<Option<(Uuid, Secret<String>, bool)>, anyhow::Error> {
     let row = sqlx::query!(
-        r#"SELECT user_id, password_hash FROM users WHERE username = $1"#,
+        r#"SELECT user_id, password_hash, disabled_at IS NOT NULL AS "disabled!" FROM users WHERE username = $1"#,
         username,
     )
     .fetch_optional(pool)
     .await
     .context("Unable to perform a query to retrive stored credentials.")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    .map(|row| (row.user_id, Secret::new(row.password_hash), row.disabled));
 
     Ok(row)
 }
@@ -87,28 +97,81 @@ fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
-pub async fn change_password(
+#[tracing::instrument(name = "Change password", skip(password, executor))]
+pub async fn change_password<'e>(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    executor: impl PgExecutor<'e>,
 ) -> Result<(), anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
         .context("Failed to hash password")?;
 
     sqlx::query!(
-        r#"UPDATE users SET password_hash = $1 WHERE user_id = $2"#,
+        r#"UPDATE users SET password_hash = $1, must_change_password = false WHERE user_id = $2"#,
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
 
     Ok(())
 }
 
+/// Set on the seeded admin, whose password is public, until they change it.
+#[tracing::instrument(skip(pool))]
+pub async fn must_change_password(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT must_change_password FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether the user must change their password.")?;
+
+    Ok(row.must_change_password)
+}
+
+/// Store a new user with the given password. Returns `None` if the username
+/// is already taken.
+///
+/// Users without an email address cannot reset their password.
+#[tracing::instrument(name = "Create user", skip(transaction, password))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: Option<&str>,
+    password: Secret<String>,
+    role: Role,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        email,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to store a new user.")?;
+
+    Ok(row.map(|r| r.user_id))
+}
+
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
 
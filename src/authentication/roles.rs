@@ -0,0 +1,112 @@
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use std::future::{ready, Ready};
+use std::marker::PhantomData;
+
+/// What an admin user is allowed to do. Each role can do everything the
+/// roles before it can:
+///
+/// - viewers can look around the admin area;
+/// - editors can also write and publish issues and manage subscribers;
+/// - owners can also manage the other users.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Result<Role, String> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!("`{}` is not a valid role.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The lowest role allowed through a [`RequireRole`] extractor.
+pub trait MinimumRole {
+    const ROLE: Role;
+}
+
+pub struct Editors;
+
+impl MinimumRole for Editors {
+    const ROLE: Role = Role::Editor;
+}
+
+pub struct Owners;
+
+impl MinimumRole for Owners {
+    const ROLE: Role = Role::Owner;
+}
+
+/// Rejects the request with a `403 Forbidden` unless the logged-in user has
+/// at least the role of `R`, e.g. `_: RequireRole<Editors>`.
+///
+/// The role is set by `reject_anonymous_users`, so this only works behind it.
+pub struct RequireRole<R: MinimumRole>(PhantomData<R>);
+
+impl<R: MinimumRole> FromRequest for RequireRole<R> {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let role = req.extensions().get::<Role>().copied();
+        let result = match role {
+            Some(role) if role >= R::ROLE => Ok(RequireRole(PhantomData)),
+            _ => Err(forbidden(R::ROLE)),
+        };
+        ready(result)
+    }
+}
+
+fn forbidden(required: Role) -> actix_web::Error {
+    let resp = HttpResponse::Forbidden()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<p>You need to be an {} to do this.</p>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>"#,
+            required
+        ));
+    let e = anyhow::anyhow!("The user does not have the {} role", required);
+    InternalError::from_response(e, resp).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Role::Viewer < Role::Editor);
+        assert!(Role::Editor < Role::Owner);
+    }
+
+    #[test]
+    fn roles_round_trip_through_their_name() {
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            assert_eq!(Role::parse(role.as_str()).unwrap(), role);
+        }
+        assert!(Role::parse("admin").is_err());
+    }
+}
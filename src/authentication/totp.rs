@@ -0,0 +1,459 @@
+use super::hash_token;
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const ISSUER: &str = "zero2prod";
+const SECRET_LENGTH: usize = 20;
+const NONCE_LENGTH: usize = 12;
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+const RECOVERY_CODES: usize = 10;
+/// Wrong second factors in a row before the user is locked out for 15
+/// minutes. After that, every wrong one locks them out again until they get
+/// it right.
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+/// Encrypts the TOTP secrets at rest.
+pub struct TotpKey(Aes256Gcm);
+
+impl TotpKey {
+    /// The key is 32 hex-encoded bytes.
+    pub fn parse(key: &Secret<String>) -> Result<Self, anyhow::Error> {
+        let key = hex::decode(key.expose_secret()).context("The TOTP key is not valid hex")?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("The TOTP key must be 32 bytes long"))?;
+        Ok(Self(cipher))
+    }
+
+    /// The ciphertext is bound to the user, so that it cannot be moved to
+    /// another account.
+    fn encrypt(&self, user_id: Uuid, secret: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: secret,
+                    aad: user_id.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt a TOTP secret"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, user_id: Uuid, encrypted: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        if encrypted.len() < NONCE_LENGTH {
+            anyhow::bail!("The encrypted TOTP secret is too short");
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        self.0
+            .decrypt(
+                nonce.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad: user_id.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt a TOTP secret"))
+    }
+}
+
+pub enum TotpStatus {
+    Disabled,
+    /// Enrolment has started, but no code has been checked yet. Holds the
+    /// base32-encoded secret.
+    Enrolling(Secret<String>),
+    Enabled {
+        recovery_codes_left: i64,
+    },
+}
+
+#[tracing::instrument(skip(pool, key))]
+pub async fn get_totp_status(
+    pool: &PgPool,
+    key: &TotpKey,
+    user_id: Uuid,
+) -> Result<TotpStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret, totp_enabled_at IS NOT NULL AS "enabled!",
+            (SELECT COUNT(*) FROM totp_recovery_codes c WHERE c.user_id = u.user_id) AS "recovery_codes_left!"
+        FROM users u
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the TOTP status of the user")?;
+
+    Ok(match row.totp_secret {
+        None => TotpStatus::Disabled,
+        Some(_) if row.enabled => TotpStatus::Enabled {
+            recovery_codes_left: row.recovery_codes_left,
+        },
+        Some(encrypted) => {
+            let secret = key.decrypt(user_id, &encrypted)?;
+            TotpStatus::Enrolling(Secret::new(encode_secret(&secret)))
+        }
+    })
+}
+
+/// Whether the user has to enter a code after their password.
+#[tracing::instrument(skip(pool))]
+pub async fn has_totp(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_enabled_at IS NOT NULL AS "enabled!" FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether the user has TOTP enabled")?;
+    Ok(row.enabled)
+}
+
+/// Generate a new secret for the user. It is only used at login once a code
+/// has been checked with `enable_totp`. Returns `false` if TOTP is already
+/// enabled.
+#[tracing::instrument(skip(pool, key))]
+pub async fn begin_totp_enrolment(
+    pool: &PgPool,
+    key: &TotpKey,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let secret: [u8; SECRET_LENGTH] = rand::thread_rng().gen();
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET totp_secret = $2, totp_last_step = NULL
+        WHERE user_id = $1 AND totp_enabled_at IS NULL
+        "#,
+        user_id,
+        key.encrypt(user_id, &secret)?
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the TOTP secret")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Enable TOTP if `code` matches the secret being enrolled. Returns the new
+/// recovery codes: only their hashes are stored, so this is the one and only
+/// chance to show them.
+#[tracing::instrument(skip(pool, key, code))]
+pub async fn enable_totp(
+    pool: &PgPool,
+    key: &TotpKey,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<Secret<String>>>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret AS "totp_secret!" FROM users
+        WHERE user_id = $1 AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the TOTP secret")?;
+    let secret = match row {
+        Some(row) => key.decrypt(user_id, &row.totp_secret)?,
+        None => return Ok(None),
+    };
+    if matching_step(&secret, code.trim(), None).is_none() {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = now() WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enable TOTP")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the previous recovery codes")?;
+    let mut recovery_codes = Vec::with_capacity(RECOVERY_CODES);
+    for _ in 0..RECOVERY_CODES {
+        let recovery_code = generate_recovery_code();
+        sqlx::query!(
+            "INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+            user_id,
+            hash_token(&normalize_recovery_code(&recovery_code))
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to store a recovery code")?;
+        recovery_codes.push(Secret::new(recovery_code));
+    }
+    transaction.commit().await?;
+
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn disable_totp(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL,
+            totp_failed_attempts = 0, totp_last_failed_at = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable TOTP")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the recovery codes")?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub enum SecondFactor {
+    Accepted,
+    Rejected,
+    /// Too many wrong codes: the code was not checked, or this was the last
+    /// wrong one allowed.
+    LockedOut,
+}
+
+/// Check the second factor of a user with TOTP enabled: either a code from
+/// their authenticator app, which cannot be used twice, or one of their
+/// recovery codes, which is then used up.
+#[tracing::instrument(skip(pool, key, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    key: &TotpKey,
+    user_id: Uuid,
+    code: &str,
+) -> Result<SecondFactor, anyhow::Error> {
+    // The attempt is counted as a failure before checking the code, so that
+    // concurrent guesses cannot get past the limit.
+    let failed_attempts = match count_failed_attempt(pool, user_id).await? {
+        Some(failed_attempts) => failed_attempts,
+        None => return Ok(SecondFactor::LockedOut),
+    };
+    if !check_second_factor(pool, key, user_id, code).await? {
+        return Ok(if failed_attempts >= MAX_FAILED_ATTEMPTS {
+            SecondFactor::LockedOut
+        } else {
+            SecondFactor::Rejected
+        });
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE users SET totp_failed_attempts = 0, totp_last_failed_at = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to reset the failed second factor attempts")?;
+    Ok(SecondFactor::Accepted)
+}
+
+/// `None` if the user is locked out.
+async fn count_failed_attempt(pool: &PgPool, user_id: Uuid) -> Result<Option<i32>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_failed_attempts = totp_failed_attempts + 1, totp_last_failed_at = now()
+        WHERE user_id = $1
+            AND (totp_failed_attempts < $2 OR totp_last_failed_at < now() - interval '15 minutes')
+        RETURNING totp_failed_attempts
+        "#,
+        user_id,
+        MAX_FAILED_ATTEMPTS
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to count a second factor attempt")?;
+    Ok(row.map(|r| r.totp_failed_attempts))
+}
+
+async fn check_second_factor(
+    pool: &PgPool,
+    key: &TotpKey,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return use_recovery_code(pool, user_id, code).await;
+    }
+
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret AS "totp_secret!", totp_last_step FROM users
+        WHERE user_id = $1 AND totp_enabled_at IS NOT NULL AND disabled_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(false),
+    };
+    let secret = key.decrypt(user_id, &row.totp_secret)?;
+    let step = match matching_step(&secret, code, row.totp_last_step) {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    // Two requests racing with the same code: only one of them gets in.
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET totp_last_step = $2
+        WHERE user_id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+        "#,
+        user_id,
+        step
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the TOTP time step")?;
+    Ok(result.rows_affected() == 1)
+}
+
+async fn use_recovery_code(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM totp_recovery_codes c
+        USING users u
+        WHERE c.user_id = $1 AND c.code_hash = $2
+            AND u.user_id = c.user_id AND u.totp_enabled_at IS NOT NULL AND u.disabled_at IS NULL
+        "#,
+        user_id,
+        hash_token(&normalize_recovery_code(code))
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// The `otpauth://` URI that authenticator apps read from a QR code.
+pub fn provisioning_uri(username: &str, secret: &Secret<String>) -> String {
+    let label = urlencoding::encode(&format!("{}:{}", ISSUER, username)).into_owned();
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        label,
+        secret.expose_secret(),
+        ISSUER,
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+/// The code for the given time step, as described in RFC 6238.
+pub fn totp_code(secret: &[u8], step: u64) -> String {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+pub fn current_totp_step() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before 1970")
+        .as_secs()
+        / STEP_SECONDS
+}
+
+/// Codes from the previous and next time steps are accepted too, to allow for
+/// clock drift, but never for a step at or before `last_step`.
+fn matching_step(secret: &[u8], code: &str, last_step: Option<i64>) -> Option<i64> {
+    let now = current_totp_step();
+    [now - 1, now, now + 1]
+        .into_iter()
+        .filter(|step| last_step.map_or(true, |last| *step as i64 > last))
+        .find(|step| totp_code(secret, *step) == code)
+        .map(|step| step as i64)
+}
+
+fn encode_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+}
+
+/// Formatted as `xxxx-xxxx-xxxx-xxxx`.
+fn generate_recovery_code() -> String {
+    let random_bytes: [u8; 8] = rand::thread_rng().gen();
+    let code = hex::encode(random_bytes);
+    format!(
+        "{}-{}-{}-{}",
+        &code[..4],
+        &code[4..8],
+        &code[8..12],
+        &code[12..]
+    )
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_recovery_code, totp_code};
+
+    // From RFC 6238, appendix B, keeping the last 6 digits.
+    #[test]
+    fn totp_codes_match_the_rfc_test_vectors() {
+        let secret = b"12345678901234567890";
+        assert_eq!(totp_code(secret, 59 / 30), "287082");
+        assert_eq!(totp_code(secret, 1111111109 / 30), "081804");
+        assert_eq!(totp_code(secret, 1234567890 / 30), "005924");
+        assert_eq!(totp_code(secret, 20000000000 / 30), "353130");
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(
+            normalize_recovery_code(" 1A2b-3c4d-5e6f-7a8b "),
+            "1a2b3c4d5e6f7a8b"
+        );
+    }
+}
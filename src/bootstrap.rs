@@ -0,0 +1,100 @@
+use crate::authentication::{create_user, hash_token, Role};
+use crate::configuration::BootstrapSettings;
+use anyhow::Context;
+use rand::Rng;
+use secrecy::Secret;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub enum Bootstrap {
+    /// There already is at least one user.
+    NotNeeded,
+    /// The first owner was created from the bootstrap settings.
+    OwnerCreated(Uuid),
+    /// The first owner has to be created through the setup page, using this
+    /// token. Only its hash is stored.
+    SetupPending(Secret<String>),
+}
+
+/// Make sure that someone can log in on a fresh installation.
+///
+/// If there is no user yet, the first owner is created from the bootstrap
+/// settings when they are set. Otherwise a new one-time token is issued for
+/// the setup page, replacing any previous one.
+#[tracing::instrument(name = "Bootstrap the first user", skip_all)]
+pub async fn bootstrap(
+    pool: &PgPool,
+    settings: Option<&BootstrapSettings>,
+) -> Result<Bootstrap, anyhow::Error> {
+    if has_users(pool).await? {
+        return Ok(Bootstrap::NotNeeded);
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if lock_users(&mut transaction).await? {
+        return Ok(Bootstrap::NotNeeded);
+    }
+
+    let outcome = match settings {
+        Some(settings) => {
+            let user_id = create_user(
+                &mut transaction,
+                &settings.username,
+                settings.email.as_deref(),
+                settings.password.clone(),
+                Role::Owner,
+            )
+            .await?
+            .context("The bootstrap username is already taken")?;
+            Bootstrap::OwnerCreated(user_id)
+        }
+        None => {
+            let random_bytes: [u8; 32] = rand::thread_rng().gen();
+            let token = hex::encode(random_bytes);
+            sqlx::query!("DELETE FROM setup_tokens")
+                .execute(&mut transaction)
+                .await
+                .context("Failed to delete the previous setup token")?;
+            sqlx::query!(
+                "INSERT INTO setup_tokens (token_hash, created_at) VALUES ($1, now())",
+                hash_token(&token)
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to store the setup token")?;
+            Bootstrap::SetupPending(Secret::new(token))
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the bootstrap")?;
+
+    Ok(outcome)
+}
+
+/// Takes no lock, so that checking an installation that is already set up
+/// cannot hold up writes to the users table.
+pub(crate) async fn has_users<'e>(executor: impl PgExecutor<'e>) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT EXISTS (SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(executor)
+        .await
+        .context("Failed to check whether there are users")?;
+
+    Ok(row.exists)
+}
+
+/// Lock the users table until the end of the transaction, then check again
+/// whether there are users. Keeps two instances starting at the same time, or
+/// two setup requests, from both creating the first owner.
+pub(crate) async fn lock_users(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to lock the users table")?;
+    has_users(transaction).await
+}
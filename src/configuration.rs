@@ -7,6 +7,7 @@ use sqlx::{
 };
 
 use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, FileSinkTransport, PostmarkTransport, SmtpTransport};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -14,6 +15,17 @@ pub struct Settings {
     pub database: DBSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    /// The first owner, created on startup if there is no user yet.
+    pub bootstrap: Option<BootstrapSettings>,
+}
+
+/// Set through `APP_BOOTSTRAP__USERNAME`, `APP_BOOTSTRAP__PASSWORD` and,
+/// optionally, `APP_BOOTSTRAP__EMAIL`.
+#[derive(serde::Deserialize, Clone)]
+pub struct BootstrapSettings {
+    pub username: String,
+    pub password: Secret<String>,
+    pub email: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -23,6 +35,8 @@ pub struct AppSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// 32 hex-encoded bytes, used to encrypt the TOTP secrets of the users.
+    pub totp_encryption_key: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -38,13 +52,82 @@ pub struct DBSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
+    /// Shared with the provider to authenticate its webhooks.
+    pub webhook_secret: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file_sink_directory: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    Postmark,
+    Smtp,
+    File,
+}
+
+impl Default for EmailTransportKind {
+    fn default() -> Self {
+        Self::Postmark
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub require_tls: bool,
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+
+        match self.transport {
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                Box::new(PostmarkTransport::new(
+                    self.base_url,
+                    self.authorization_token,
+                    timeout,
+                )),
+            ),
+            EmailTransportKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("Missing `email_client.smtp` settings for the SMTP transport.");
+                let credentials = smtp.username.zip(smtp.password);
+                let transport = SmtpTransport::new(
+                    &smtp.host,
+                    smtp.port,
+                    credentials,
+                    smtp.require_tls,
+                    timeout,
+                )
+                .expect("Failed to build the SMTP transport.");
+
+                EmailClient::new(sender_email, Box::new(transport))
+            }
+            EmailTransportKind::File => {
+                let directory = self
+                    .file_sink_directory
+                    .expect("Missing `email_client.file_sink_directory` for the file transport.");
+
+                EmailClient::new(sender_email, Box::new(FileSinkTransport::new(directory)))
+            }
+        }
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
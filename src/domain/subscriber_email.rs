@@ -4,9 +4,11 @@ use validator::validate_email;
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// Addresses are lowercased, so that they are stored and compared the
+    /// same way whatever case they were typed or reported in.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
         if validate_email(&s) {
-            Ok(Self(s))
+            Ok(Self(s.to_lowercase()))
         } else {
             Err(format!("{} is not a valid subscriber email.", s))
         }
@@ -63,4 +65,10 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn emails_are_lowercased() {
+        let email = SubscriberEmail::parse("Ursula@Domain.com".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
 }
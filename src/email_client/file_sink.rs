@@ -0,0 +1,74 @@
+use super::smtp::build_message;
+use super::{Email, EmailTransport};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes every email into a maildir instead of sending it, for local
+/// development. Point any maildir-aware mail client at the directory to
+/// read them.
+pub struct FileSinkTransport {
+    directory: PathBuf,
+}
+
+impl FileSinkTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        FileSinkTransport {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileSinkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<String, anyhow::Error> {
+        let message = build_message(email)?;
+
+        let tmp_dir = self.directory.join("tmp");
+        let new_dir = self.directory.join("new");
+        tokio::fs::create_dir_all(&tmp_dir).await?;
+        tokio::fs::create_dir_all(&new_dir).await?;
+
+        // Maildir delivery: write the message in `tmp` then move it into `new`,
+        // so that readers never see a partially written file.
+        let file_name = format!(
+            "{}.{}.zero2prod",
+            chrono::Utc::now().timestamp(),
+            Uuid::new_v4()
+        );
+        tokio::fs::write(tmp_dir.join(&file_name), message.formatted()).await?;
+        tokio::fs::rename(tmp_dir.join(&file_name), new_dir.join(&file_name)).await?;
+
+        Ok(new_dir.join(file_name).display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileSinkTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn send_email_writes_the_message_into_the_maildir() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+            Box::new(FileSinkTransport::new(&directory)),
+        );
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        let path = email_client
+            .send_email(&recipient, "Welcome!", "<p>HTML</p>", "Text")
+            .await
+            .unwrap();
+
+        assert!(path.starts_with(directory.join("new").to_str().unwrap()));
+        let message = std::fs::read_to_string(&path).unwrap();
+        assert!(message.contains("To: ursula@example.com"));
+        assert!(message.contains("Subject: Welcome!"));
+        assert_eq!(std::fs::read_dir(directory.join("tmp")).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
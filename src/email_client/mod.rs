@@ -0,0 +1,372 @@
+mod file_sink;
+mod postmark;
+mod smtp;
+
+use crate::domain::SubscriberEmail;
+
+pub use file_sink::FileSinkTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+/// An email, as handed over by `EmailClient` to an `EmailTransport`.
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    /// Advertised through the RFC 8058 `List-Unsubscribe` headers when set.
+    pub unsubscribe_link: Option<&'a str>,
+}
+
+/// The outcome of a single email within a batch: the provider's response if
+/// it was accepted, the reason it was rejected otherwise.
+pub type BatchOutcome = Result<String, String>;
+
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// Deliver an email, returning the provider's response on success.
+    async fn send(&self, email: &Email<'_>) -> Result<String, anyhow::Error>;
+
+    /// Deliver several emails, returning one outcome per email in the same
+    /// order. An error means that the batch as a whole could not be sent.
+    ///
+    /// Transports without a batch API send the emails one at a time.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<BatchOutcome>, anyhow::Error> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcomes.push(self.send(email).await.map_err(|e| e.to_string()));
+        }
+
+        Ok(outcomes)
+    }
+}
+
+/// A newsletter issue addressed to one subscriber, as sent by `EmailClient::send_batch`.
+pub struct Newsletter<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_link: &'a str,
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: Box<dyn EmailTransport>) -> Self {
+        EmailClient { sender, transport }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<String, anyhow::Error> {
+        let email = Email {
+            from: &self.sender,
+            to: recipient,
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            unsubscribe_link: None,
+        };
+
+        self.transport.send(&email).await
+    }
+
+    /// Send a newsletter issue, advertising RFC 8058 one-click unsubscription
+    /// through the `List-Unsubscribe` and `List-Unsubscribe-Post` headers.
+    pub async fn send_newsletter_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: &str,
+    ) -> Result<String, anyhow::Error> {
+        let email = Email {
+            from: &self.sender,
+            to: recipient,
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            unsubscribe_link: Some(unsubscribe_link),
+        };
+
+        self.transport.send(&email).await
+    }
+
+    /// Send newsletters to several subscribers at once. See `EmailTransport::send_batch`.
+    pub async fn send_batch(
+        &self,
+        newsletters: &[Newsletter<'_>],
+    ) -> Result<Vec<BatchOutcome>, anyhow::Error> {
+        let emails: Vec<_> = newsletters
+            .iter()
+            .map(|newsletter| Email {
+                from: &self.sender,
+                to: newsletter.recipient,
+                subject: newsletter.subject,
+                html_body: newsletter.html_content,
+                text_body: newsletter.text_content,
+                unsubscribe_link: Some(newsletter.unsubscribe_link),
+            })
+            .collect();
+
+        self.transport.send_batch(&emails).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::Fake;
+    use fake::Faker;
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::Request;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::domain::SubscriberEmail;
+
+    use super::{EmailClient, Newsletter, PostmarkTransport};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, req: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&req.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        );
+
+        EmailClient::new(email(), Box::new(transport))
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_newsletter_email_sets_the_list_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_newsletter_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                "https://example.com/unsubscribe",
+            )
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcom = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcom);
+    }
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcom = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcom);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let resp = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(resp)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let outcom = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcom);
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_an_outcome_per_recipient() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+        let newsletters: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Newsletter {
+                recipient,
+                subject: "Newsletter title",
+                html_content: "<p>Newsletter body as HTML</p>",
+                text_content: "Newsletter body as plain text",
+                unsubscribe_link: "https://example.com/unsubscribe",
+            })
+            .collect();
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "Inactive recipient"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_batch(&newsletters).await.unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[1]["To"], recipients[1].as_ref());
+        assert_ok!(&outcomes[0]);
+        assert!(outcomes[1]
+            .as_ref()
+            .unwrap_err()
+            .contains("Inactive recipient"));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let newsletter = Newsletter {
+            recipient: &recipient,
+            subject: &subject(),
+            html_content: &content(),
+            text_content: &content(),
+            unsubscribe_link: "https://example.com/unsubscribe",
+        };
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_batch(&[newsletter]).await;
+
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,132 @@
+use super::{BatchOutcome, Email, EmailTransport};
+use anyhow::Context;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+
+/// Sends emails through Postmark's `/email` and `/email/batch` JSON APIs.
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: String,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+
+        PostmarkTransport {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<String, anyhow::Error> {
+        let provider_response = self
+            .http_client
+            .post(format!("{}/email", self.base_url))
+            .json(&request_body(email))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(provider_response)
+    }
+
+    /// Postmark accepts a batch as a whole and then reports an `ErrorCode` for
+    /// each message, in the order in which they were submitted.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<BatchOutcome>, anyhow::Error> {
+        let req_body: Vec<_> = emails.iter().map(request_body).collect();
+
+        let responses = self
+            .http_client
+            .post(format!("{}/email/batch", self.base_url))
+            .json(&req_body)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<serde_json::Value>>()
+            .await
+            .context("Failed to parse the response to a batch of emails.")?;
+
+        if responses.len() != emails.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} emails.",
+                responses.len(),
+                emails.len()
+            );
+        }
+
+        let outcomes = responses
+            .into_iter()
+            .map(|response| match response["ErrorCode"].as_i64() {
+                Some(0) => Ok(response.to_string()),
+                _ => Err(format!(
+                    "Postmark rejected the email ({}): {}",
+                    response["ErrorCode"], response["Message"]
+                )),
+            })
+            .collect();
+
+        Ok(outcomes)
+    }
+}
+
+fn request_body<'a>(email: &Email<'a>) -> SendEmailRequest<'a> {
+    let mut headers = vec![];
+    if let Some(unsubscribe_link) = email.unsubscribe_link {
+        headers.push(EmailHeader {
+            name: "List-Unsubscribe",
+            value: format!("<{}>", unsubscribe_link),
+        });
+        headers.push(EmailHeader {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click".into(),
+        });
+    }
+
+    SendEmailRequest {
+        from: email.from.as_ref(),
+        to: email.to.as_ref(),
+        subject: email.subject,
+        html_body: email.html_body,
+        text_body: email.text_body,
+        headers,
+    }
+}
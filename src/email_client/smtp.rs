@@ -0,0 +1,218 @@
+use super::{Email, EmailTransport};
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+/// Sends emails to an SMTP relay.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, Secret<String>)>,
+        require_tls: bool,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        }
+        .port(port)
+        .timeout(Some(timeout));
+
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(SmtpTransport {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<String, anyhow::Error> {
+        let message = build_message(email)?;
+        let response = self.mailer.send(message).await?;
+
+        Ok(response.message().collect::<Vec<_>>().join("\n"))
+    }
+}
+
+/// Render an email as a multipart/alternative MIME message.
+pub(super) fn build_message(email: &Email<'_>) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(email.from.as_ref().parse::<Mailbox>()?)
+        .to(email.to.as_ref().parse::<Mailbox>()?)
+        .subject(email.subject);
+
+    if let Some(unsubscribe_link) = email.unsubscribe_link {
+        builder = builder
+            .header(ListUnsubscribe(format!("<{}>", unsubscribe_link)))
+            .header(ListUnsubscribePost);
+    }
+
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        email.text_body.to_owned(),
+        email.html_body.to_owned(),
+    ))?;
+
+    Ok(message)
+}
+
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_owned()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmtpTransport;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use claim::assert_err;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// A bare-bones SMTP server accepting a single message, which is
+    /// returned once the client has quit.
+    async fn spawn_smtp_stand_in(data_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        data_reply
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                        continue;
+                    }
+                } else if line.starts_with("EHLO") {
+                    "250 localhost"
+                } else if line == "DATA" {
+                    in_data = true;
+                    "354 Start mail input"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 OK"
+                };
+                writer
+                    .write_all(format!("{}\r\n", reply).as_bytes())
+                    .await
+                    .unwrap();
+            }
+
+            data
+        });
+
+        (port, handle)
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            None,
+            false,
+            std::time::Duration::from_secs(1),
+        )
+        .unwrap();
+
+        EmailClient::new(
+            SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+            Box::new(transport),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message_over_smtp() {
+        let (port, server) = spawn_smtp_stand_in("250 OK queued as 42").await;
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        let response = email_client(port)
+            .send_newsletter_email(
+                &recipient,
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                "https://example.com/unsubscribe",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response, "OK queued as 42");
+        let data = server.await.unwrap();
+        assert!(data.contains("From: newsletter@example.com"));
+        assert!(data.contains("To: ursula@example.com"));
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(data.contains("Newsletter body as plain text"));
+        assert!(data.contains("<p>Newsletter body as HTML</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_rejects_the_message() {
+        let (port, _server) = spawn_smtp_stand_in("554 Transaction failed").await;
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        let outcome = email_client(port)
+            .send_email(&recipient, "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
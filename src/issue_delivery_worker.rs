@@ -1,34 +1,49 @@
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailClient, Newsletter};
+use crate::routes::{add_tracking, archive_link, unsubscribe_link, with_web_view_link};
+use crate::startup::HmacSecret;
+use crate::templating::{IssueTemplate, MergeFields};
 use crate::{configuration::Settings, startup::get_connection_pool};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::Rng;
 use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::time::Duration;
-use tracing::field::display;
 use tracing::Span;
 use uuid::Uuid;
 
-pub async fn run_worker_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
-    let conn_pool = get_connection_pool(&config.database);
-    let sender_email = config
-        .email_client
-        .sender()
-        .expect("Invalid sender email address.");
+/// The maximum number of tasks sent together in a single batch.
+pub const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// A task is moved to `issue_delivery_dead_letters` once it has failed this many times.
+pub const MAX_DELIVERY_ATTEMPTS: i16 = 5;
 
-    let timeout = config.email_client.timeout();
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
 
-    let email_client = EmailClient::new(
-        config.email_client.base_url,
-        sender_email,
-        config.email_client.authorization_token,
-        timeout,
-    );
+pub async fn run_worker_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
+    let conn_pool = get_connection_pool(&config.database);
+    let email_client = config.email_client.client();
+    let hmac_secret = HmacSecret(config.application.hmac_secret);
 
-    worker_loop(&conn_pool, email_client).await
+    worker_loop(
+        &conn_pool,
+        email_client,
+        &config.application.base_url,
+        &hmac_secret,
+    )
+    .await
 }
 
-async fn worker_loop(pool: &PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: &PgPool,
+    email_client: EmailClient,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(pool, &email_client).await {
+        match try_execute_task(pool, &email_client, base_url, hmac_secret).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -40,99 +55,435 @@ async fn worker_loop(pool: &PgPool, email_client: EmailClient) -> Result<(), any
     }
 }
 
-enum ExecutionOutcome {
+pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
-    ),
-    err
-)]
-async fn try_execute_task(
+/// Lock up to `DELIVERY_BATCH_SIZE` due tasks and send them in a single batch.
+/// Each task is then completed according to its own outcome, so that a
+/// partially failed batch only retries the emails that were not accepted.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
+    let (mut tx, tasks) = match dequeue_tasks(pool).await? {
+        Some(dequeued) => dequeued,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    Span::current().record("n_tasks", &tasks.len());
+
+    let subscribers = get_confirmed_subscribers(pool, &tasks).await?;
+    let issues = get_issues(pool, &tasks).await?;
+
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    let mut recipients = vec![];
+    for task in &tasks {
+        let subscriber = match subscribers.get(&(task.issue_id, task.email.clone())) {
+            Some(subscriber) => subscriber,
+            None => {
+                tracing::info!(subscriber_email = %task.email, "Skipping a subscriber who is no longer confirmed.");
+                outcomes.push((task, DeliveryOutcome::Skipped));
+                continue;
+            }
+        };
+
+        let email = match SubscriberEmail::parse(task.email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::error!(error.cause_chain =?e, error.message = %e, subscriber_email = %task.email, "Skipping a confirmed subscriber. Their stored contact details are invalid");
+                outcomes.push((task, DeliveryOutcome::Failed(e)));
+                continue;
+            }
+        };
+        let issue = issues
+            .get(&task.issue_id)
+            .context("The newsletter issue of a queued task is missing.")?;
+        let template = match &issue.template {
+            Ok(template) => template,
+            Err(e) => {
+                tracing::error!(error.message = %e, "Skipping an issue whose content is not a valid template.");
+                outcomes.push((task, DeliveryOutcome::Failed(e.clone())));
+                continue;
+            }
+        };
+        let unsubscribe_link = unsubscribe_link(base_url, subscriber.id, hmac_secret);
+        let (html_content, text_content) = template.render(&MergeFields {
+            name: &subscriber.name,
+            email: email.as_ref(),
+            unsubscribe_url: &unsubscribe_link,
+            confirmed_at: subscriber.confirmed_at,
+        });
+        let (html_content, text_content) = match &issue.archive_slug {
+            Some(slug) => {
+                with_web_view_link(&html_content, &text_content, &archive_link(base_url, slug))
+            }
+            None => (html_content, text_content),
+        };
+        let html_content = if issue.tracking {
+            add_tracking(
+                &html_content,
+                base_url,
+                task.issue_id,
+                subscriber.id,
+                hmac_secret,
+            )
+        } else {
+            html_content
+        };
+        recipients.push(Recipient {
+            task,
+            email,
+            subject: &issue.title,
+            html_content,
+            text_content,
+            unsubscribe_link,
+        });
     }
 
-    let (tx, issue_id, email) = task.unwrap();
-
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(error.cause_chain =?e, error.message = %e, "Failed to deliver issue to confirmed subscriber. Skipping.");
+    let newsletters: Vec<_> = recipients
+        .iter()
+        .map(|r| Newsletter {
+            recipient: &r.email,
+            subject: r.subject,
+            html_content: &r.html_content,
+            text_content: &r.text_content,
+            unsubscribe_link: &r.unsubscribe_link,
+        })
+        .collect();
+
+    if !newsletters.is_empty() {
+        match email_client.send_batch(&newsletters).await {
+            Ok(results) => {
+                for (Recipient { task, .. }, result) in recipients.iter().zip(results) {
+                    let outcome = match result {
+                        Ok(provider_response) => DeliveryOutcome::Sent(provider_response),
+                        Err(e) => {
+                            tracing::error!(error.message = %e, subscriber_email = %task.email, "Failed to deliver issue to confirmed subscriber.");
+                            DeliveryOutcome::Retry(e)
+                        }
+                    };
+                    outcomes.push((task, outcome));
+                }
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain =?e, error.message = %e, "Failed to deliver a batch of issues.");
+                for Recipient { task, .. } in &recipients {
+                    outcomes.push((task, DeliveryOutcome::Retry(e.to_string())));
+                }
             }
-        }
-        Err(e) => {
-            tracing::error!(error.cause_chain =?e, error.message = %e, "Skipping a confirmed subscriber. Their stored contact details are invalid");
         }
     }
 
-    delete_task(tx, issue_id, &email).await?;
+    for (task, outcome) in outcomes {
+        complete_task(&mut tx, task, outcome).await?;
+    }
+    tx.commit().await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// An email ready to go, with the merge fields of the issue filled in.
+struct Recipient<'a> {
+    task: &'a DeliveryTask,
+    email: SubscriberEmail,
+    subject: &'a str,
+    html_content: String,
+    text_content: String,
+    unsubscribe_link: String,
+}
+
+struct DeliveryTask {
+    issue_id: Uuid,
+    email: String,
+    n_retries: i16,
+}
+
+enum DeliveryOutcome {
+    Sent(String),
+    Retry(String),
+    Failed(String),
+    Skipped,
+}
+
+/// Only tasks of issues whose `publish_at` has passed are picked up, so that
+/// scheduled issues wait in the queue until they are due.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, Vec<DeliveryTask>)>, anyhow::Error> {
     let mut tx = pool.begin().await?;
-    let row = sqlx::query!(r#"SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_queue FOR UPDATE SKIP LOCKED LIMIT 1"#)
-    .fetch_optional(&mut tx).await?;
+    let tasks: Vec<_> = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.n_retries
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.execute_after <= now() AND i.publish_at <= now() AND i.cancelled_at IS NULL
+        FOR UPDATE OF q SKIP LOCKED
+        LIMIT $1
+        "#,
+        DELIVERY_BATCH_SIZE
+    )
+    .fetch_all(&mut tx)
+    .await?
+    .into_iter()
+    .map(|row| DeliveryTask {
+        issue_id: row.newsletter_issue_id,
+        email: row.subscriber_email,
+        n_retries: row.n_retries,
+    })
+    .collect();
 
-    if let Some(row) = row {
-        Ok(Some((tx, row.newsletter_issue_id, row.subscriber_email)))
-    } else {
+    if tasks.is_empty() {
         Ok(None)
+    } else {
+        Ok(Some((tx, tasks)))
     }
 }
 
+/// Record the outcome of a delivery attempt and update the queue accordingly:
+/// sent tasks are removed, failed ones are rescheduled with exponential backoff
+/// or moved to the dead letters once they have used all of their attempts.
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut tx: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+async fn complete_task(
+    tx: &mut PgTransaction,
+    task: &DeliveryTask,
+    outcome: DeliveryOutcome,
 ) -> Result<(), anyhow::Error> {
-    sqlx::query!(r#"DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1 AND subscriber_email = $2"#, issue_id, email)
-    .execute(&mut tx).await?;
+    let n_attempts = task.n_retries + 1;
+    let outcome = match outcome {
+        DeliveryOutcome::Retry(error) if n_attempts >= MAX_DELIVERY_ATTEMPTS => {
+            tracing::warn!(
+                "Giving up on delivering the issue after {} attempts.",
+                n_attempts
+            );
+            DeliveryOutcome::Failed(error)
+        }
+        outcome => outcome,
+    };
 
-    tx.commit().await?;
+    match &outcome {
+        DeliveryOutcome::Sent(provider_response) => {
+            record_delivery(tx, task, "sent", provider_response).await?;
+            delete_task(tx, task).await?;
+        }
+        DeliveryOutcome::Retry(error) => {
+            record_delivery(tx, task, "retrying", error).await?;
+            retry_task(tx, task).await?;
+        }
+        DeliveryOutcome::Failed(error) => {
+            record_delivery(tx, task, "failed", error).await?;
+            dead_letter_task(tx, task, n_attempts, error).await?;
+            delete_task(tx, task).await?;
+        }
+        DeliveryOutcome::Skipped => {
+            record_delivery(
+                tx,
+                task,
+                "skipped",
+                "The subscriber is no longer confirmed.",
+            )
+            .await?;
+            delete_task(tx, task).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(tx: &mut PgTransaction, task: &DeliveryTask) -> Result<(), anyhow::Error> {
+    sqlx::query!(r#"DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1 AND subscriber_email = $2"#, task.issue_id, task.email)
+    .execute(tx).await?;
 
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn retry_task(tx: &mut PgTransaction, task: &DeliveryTask) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(retry_delay(task.n_retries))?;
+
+    sqlx::query!(
+        r#"UPDATE issue_delivery_queue SET n_retries = n_retries + 1, execute_after = $3 WHERE newsletter_issue_id = $1 AND subscriber_email = $2"#,
+        task.issue_id,
+        task.email,
+        execute_after
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    tx: &mut PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"INSERT INTO issue_delivery_dead_letters (newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at) VALUES ($1, $2, $3, $4, now())"#,
+        task.issue_id,
+        task.email,
+        n_attempts,
+        error
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    tx: &mut PgTransaction,
+    task: &DeliveryTask,
+    outcome: &str,
+    provider_response: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, outcome, provider_response, n_attempts, first_attempted_at, last_attempted_at)
+        VALUES ($1, $2, $3, $4, $5, now(), now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET outcome = EXCLUDED.outcome, provider_response = EXCLUDED.provider_response, n_attempts = EXCLUDED.n_attempts, last_attempted_at = EXCLUDED.last_attempted_at
+        "#,
+        task.issue_id,
+        task.email,
+        outcome,
+        provider_response,
+        task.n_retries + 1
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Exponential backoff plus up to one base delay of random jitter:
+/// `base * 2^n_retries + rand(0..base)`, capped at `MAX_RETRY_DELAY`.
+fn retry_delay(n_retries: i16) -> Duration {
+    let exponent = n_retries.clamp(0, 16) as u32;
+    let backoff = BASE_RETRY_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY);
+    let jitter = rand::thread_rng().gen_range(0..BASE_RETRY_DELAY.as_millis() as u64);
+
+    backoff + Duration::from_millis(jitter)
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// Subscribers keyed by `(issue_id, email)`, for the tasks whose recipient
+/// is still confirmed on at least one of the lists targeted by the issue.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    tasks: &[DeliveryTask],
+) -> Result<HashMap<(Uuid, String), ConfirmedSubscriber>, anyhow::Error> {
+    let issue_ids: Vec<_> = tasks.iter().map(|task| task.issue_id).collect();
+    let emails: Vec<_> = tasks.iter().map(|task| task.email.clone()).collect();
+    let rows = sqlx::query!(
+        r#"
+        SELECT q.issue_id AS "issue_id!", s.email, s.id, s.name, s.confirmed_at
+        FROM UNNEST($1::uuid[], $2::text[]) AS q(issue_id, email)
+        JOIN subscriptions s ON s.email = q.email
+        WHERE s.status = 'confirmed' AND EXISTS (
+            SELECT 1 FROM list_subscriptions l
+            JOIN newsletter_issue_lists il ON il.list_id = l.list_id
+            WHERE l.subscriber_id = s.id
+              AND il.newsletter_issue_id = q.issue_id
+              AND l.status = 'confirmed'
+        )
+        "#,
+        &issue_ids[..],
+        &emails[..]
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let subscriber = ConfirmedSubscriber {
+                id: r.id,
+                name: r.name,
+                confirmed_at: r.confirmed_at,
+            };
+            ((r.issue_id, r.email), subscriber)
+        })
+        .collect())
+}
+
 struct NewsletterIssue {
     title: String,
-    text_content: String,
-    html_content: String,
+    /// Templates are validated when the issue is published, but issues
+    /// published before merge fields existed may not parse.
+    template: Result<IssueTemplate, String>,
+    /// The slug of the issue in the public archive, `None` for private issues.
+    archive_slug: Option<String>,
+    /// Whether links and opens are tracked.
+    tracking: bool,
 }
 
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(NewsletterIssue, r#"SELECT title, text_content, html_content FROM newsletter_issues WHERE newsletter_issue_id = $1"#, issue_id).fetch_one(pool).await?;
+async fn get_issues(
+    pool: &PgPool,
+    tasks: &[DeliveryTask],
+) -> Result<HashMap<Uuid, NewsletterIssue>, anyhow::Error> {
+    let issue_ids: Vec<_> = tasks.iter().map(|task| task.issue_id).collect();
+    let rows = sqlx::query!(
+        r#"SELECT newsletter_issue_id, title, text_content, html_content, slug, private, tracking FROM newsletter_issues WHERE newsletter_issue_id = ANY($1)"#,
+        &issue_ids[..]
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let issue = NewsletterIssue {
+                title: r.title,
+                template: IssueTemplate::parse(&r.html_content, &r.text_content),
+                archive_slug: r.slug.filter(|_| !r.private),
+                tracking: r.tracking,
+            };
+            (r.newsletter_issue_id, issue)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay, BASE_RETRY_DELAY, MAX_RETRY_DELAY};
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        for n_retries in 0..4 {
+            let delay = retry_delay(n_retries);
+            let backoff = BASE_RETRY_DELAY * 2u32.pow(n_retries as u32);
 
-    Ok(issue)
+            assert!(delay >= backoff);
+            assert!(delay < backoff + BASE_RETRY_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let delay = retry_delay(i16::MAX);
+
+        assert!(delay >= MAX_RETRY_DELAY);
+        assert!(delay < MAX_RETRY_DELAY + BASE_RETRY_DELAY);
+    }
 }
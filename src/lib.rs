@@ -1,13 +1,18 @@
 pub mod authentication;
+pub mod bootstrap;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mailing_lists;
+pub mod markdown;
 pub mod routes;
+pub mod segment;
 pub mod session_state;
 pub mod startup;
 pub mod telemetry;
+pub mod templating;
 pub mod utils;
 
 pub use startup::run;
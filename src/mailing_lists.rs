@@ -0,0 +1,191 @@
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The list everybody was subscribed to before lists existed. It is used
+/// whenever a subscription or an issue does not name a list.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListError {
+    #[error("There is no list named `{0}`.")]
+    UnknownList(String),
+    #[error("Choose at least one list.")]
+    NoList,
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+/// The lists targeted by an admin form: the default list when the field is
+/// missing altogether, the comma-separated slugs otherwise.
+pub fn list_slugs_or_default(slugs: Option<&str>) -> Vec<String> {
+    match slugs {
+        None => vec![DEFAULT_LIST_SLUG.to_owned()],
+        Some(slugs) => parse_list_slugs(slugs),
+    }
+}
+
+/// Split a comma-separated list of slugs, as typed in the admin forms.
+pub fn parse_list_slugs(slugs: &str) -> Vec<String> {
+    slugs
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_list_id<'e>(executor: impl PgExecutor<'e>, slug: &str) -> Result<Uuid, ListError> {
+    sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug)
+        .fetch_optional(executor)
+        .await?
+        .map(|r| r.list_id)
+        .ok_or_else(|| ListError::UnknownList(slug.to_owned()))
+}
+
+/// Resolve every slug, failing on the first unknown one.
+pub async fn get_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    slugs: &[String],
+) -> Result<Vec<Uuid>, ListError> {
+    if slugs.is_empty() {
+        return Err(ListError::NoList);
+    }
+    let mut list_ids = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        let list_id = get_list_id(&mut *transaction, slug).await?;
+        if !list_ids.contains(&list_id) {
+            list_ids.push(list_id);
+        }
+    }
+    Ok(list_ids)
+}
+
+/// Target a newsletter issue at the given lists.
+#[tracing::instrument(skip(transaction))]
+pub async fn set_issue_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        list_ids
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Add the subscriber to a list with the given status, returning the status
+/// of their existing membership instead when they are already on it.
+#[tracing::instrument(skip(transaction))]
+pub async fn add_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        list_id,
+        subscriber_id,
+        status
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if inserted == 1 {
+        return Ok(None);
+    }
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT status FROM list_subscriptions
+        WHERE list_id = $1 AND subscriber_id = $2
+        FOR UPDATE
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(Some(existing.status))
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn set_list_subscription_status<'e>(
+    executor: impl PgExecutor<'e>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions
+        SET status = $3
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id,
+        status
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Used by the one-click unsubscribe link, which covers every list.
+#[tracing::instrument(skip(executor))]
+pub async fn unsubscribe_from_all_lists<'e>(
+    executor: impl PgExecutor<'e>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE list_subscriptions SET status = 'unsubscribed' WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn confirm_pending_list_subscriptions<'e>(
+    executor: impl PgExecutor<'e>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_list_slugs;
+
+    #[test]
+    fn list_slugs_are_comma_separated() {
+        assert_eq!(
+            parse_list_slugs(" newsletter, releases ,,"),
+            vec!["newsletter".to_owned(), "releases".to_owned()]
+        );
+        assert!(parse_list_slugs(" ").is_empty());
+    }
+}
@@ -1,9 +1,11 @@
 use std::fmt::{Debug, Display};
 
+use secrecy::ExposeSecret;
 use tokio::task::JoinError;
+use zero2prod::bootstrap::{bootstrap, Bootstrap};
 use zero2prod::configuration;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
-use zero2prod::startup::Application;
+use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subsciber, init_subscriber};
 
 #[tokio::main]
@@ -12,6 +14,24 @@ async fn main() -> anyhow::Result<()> {
     init_subscriber(subscriber);
     // read config
     let config = configuration::get_config().expect("Unable to read config");
+    match bootstrap(
+        &get_connection_pool(&config.database),
+        config.bootstrap.as_ref(),
+    )
+    .await?
+    {
+        Bootstrap::NotNeeded => {}
+        Bootstrap::OwnerCreated(user_id) => {
+            tracing::info!(%user_id, "Created the first owner from the bootstrap settings")
+        }
+        Bootstrap::SetupPending(token) => {
+            tracing::warn!(
+                "There is no user yet: create the first owner at {}/setup?token={}",
+                config.application.base_url,
+                token.expose_secret()
+            )
+        }
+    }
     let application = Application::build(config.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
     let worker_task = tokio::spawn(run_worker_until_stopped(config));
@@ -0,0 +1,191 @@
+//! Markdown authoring of newsletter issues.
+//!
+//! The Markdown source is rendered into sanitized HTML and into a plain text
+//! alternative. Merge fields (see [`crate::templating`]) go through untouched,
+//! so `[Unsubscribe]({{unsubscribe_url}})` works as expected.
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+/// The content of an issue, written either in Markdown or as separate HTML
+/// and plain text bodies.
+pub struct IssueContent {
+    pub markdown: Option<String>,
+    pub html: String,
+    pub text: String,
+}
+
+impl IssueContent {
+    /// A non-blank Markdown source takes precedence over the HTML and plain
+    /// text bodies, which are then generated from it.
+    pub fn new(markdown: Option<String>, html: String, text: String) -> IssueContent {
+        match markdown.filter(|m| !m.trim().is_empty()) {
+            Some(markdown) => IssueContent {
+                html: render_html(&markdown),
+                text: render_text(&markdown),
+                markdown: Some(markdown),
+            },
+            None => IssueContent {
+                markdown: None,
+                html,
+                text,
+            },
+        }
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+}
+
+/// Raw HTML is allowed in the source, but scripts, styles and event handlers
+/// are stripped from the result.
+pub fn render_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser(markdown));
+    // pulldown-cmark percent-encodes the braces of merge fields in links.
+    let html = html.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+    ammonia::clean(&html)
+}
+
+/// A readable plain text version: links are followed by their URL, list items
+/// by a bullet, and raw HTML is dropped along with the content of scripts and
+/// styles.
+pub fn render_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // One entry per open list: the next number for ordered lists.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut link_text_start = 0;
+    let mut in_script = false;
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::List(first_number)) => {
+                if !lists.is_empty() {
+                    text.push('\n');
+                }
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+                text.push_str(&indent);
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        text.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::Paragraph) => {
+                text.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+            }
+            Event::End(Tag::Heading(level, _, _)) => {
+                let underline = match level {
+                    HeadingLevel::H1 => Some('='),
+                    HeadingLevel::H2 => Some('-'),
+                    _ => None,
+                };
+                if let Some(c) = underline {
+                    let length = text.lines().last().unwrap_or_default().chars().count();
+                    text.push('\n');
+                    text.extend(std::iter::repeat(c).take(length));
+                }
+                text.push_str("\n\n");
+            }
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Table(_)) => text.push('\n'),
+            Event::End(Tag::TableRow) | Event::End(Tag::TableHead) => text.push('\n'),
+            Event::End(Tag::TableCell) => text.push('\t'),
+            Event::Start(Tag::Link(_, _, _)) | Event::Start(Tag::Image(_, _, _)) => {
+                link_text_start = text.len();
+            }
+            Event::End(Tag::Link(_, url, _)) | Event::End(Tag::Image(_, url, _))
+                if text[link_text_start..] != *url =>
+            {
+                text.push_str(&format!(" ({})", url));
+            }
+            Event::Html(html) => {
+                let html = html.trim_start().to_lowercase();
+                if html.starts_with("<script") || html.starts_with("<style") {
+                    in_script = true;
+                } else if html.starts_with("</script") || html.starts_with("</style") {
+                    in_script = false;
+                }
+            }
+            Event::Text(t) | Event::Code(t) if !in_script => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----\n\n"),
+            _ => {}
+        }
+    }
+    text.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_html, render_text, IssueContent};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let html = render_html("# Title\n\nSome *emphasis* and a [link](https://example.com).");
+
+        assert_eq!(
+            html,
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em> and a <a href=\"https://example.com\" rel=\"noopener noreferrer\">link</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn dangerous_html_is_removed() {
+        let html = render_html(
+            "Hello <script>alert(1)</script><img src=\"x.png\" onerror=\"alert(1)\">\n\n[x](javascript:alert(1))",
+        );
+
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript"));
+        assert!(html.contains("<img src=\"x.png\">"));
+    }
+
+    #[test]
+    fn merge_fields_survive_rendering() {
+        let markdown = "Hi {{ name }}, [unsubscribe]({{unsubscribe_url}})";
+
+        assert!(render_html(markdown).contains(r#"<a href="{{unsubscribe_url}}""#));
+        assert!(render_html(markdown).contains("Hi {{ name }}"));
+        assert_eq!(
+            render_text(markdown),
+            "Hi {{ name }}, unsubscribe ({{unsubscribe_url}})"
+        );
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_readable_text() {
+        let text = render_text(
+            "# Title\n\nIntro with a [link](https://example.com).\n\n- one\n- two\n\n1. first\n2. second\n\nThe end <b>now</b>.",
+        );
+
+        assert_eq!(
+            text,
+            "Title\n=====\n\nIntro with a link (https://example.com).\n\n- one\n- two\n\n1. first\n2. second\n\nThe end now."
+        );
+    }
+
+    #[test]
+    fn the_two_field_mode_is_kept_without_markdown() {
+        for markdown in [None, Some("  ".to_owned())] {
+            let content = IssueContent::new(markdown, "<p>html</p>".into(), "text".into());
+
+            assert_eq!(content.markdown, None);
+            assert_eq!(content.html, "<p>html</p>");
+            assert_eq!(content.text, "text");
+        }
+    }
+}
@@ -32,7 +32,14 @@ pub async fn admin_dashboard(
     <p>Welcome {username}!</p>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/newsletter">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/drafts">Newsletter drafts</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/lists">Lists</a></li>
+        <li><a href="/admin/tokens">API tokens</a></li>
+        <li><a href="/admin/users">Users</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/security">Two-factor authentication</a></li>
         <li>
         <form name="logoutForm" action="/admin/logout" method="post">
             <input type="submit" value="Logout">
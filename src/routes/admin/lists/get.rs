@@ -0,0 +1,86 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn list_lists(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&list.name),
+            htmlescape::encode_minimal(&list.slug),
+            list.n_confirmed,
+            list.n_pending
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Lists</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Lists</h1>
+    <table>
+        <tr><th>Name</th><th>Slug</th><th>Confirmed</th><th>Pending</th></tr>
+        {rows_html}
+    </table>
+    <h2>New list</h2>
+    <form action="/admin/lists" method="post">
+        <label>Name <input type="text" name="name"></label>
+        <label>Slug <input type="text" name="slug" placeholder="lowercase-letters-digits-and-dashes"></label>
+        <button type="submit">Create</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct ListSummary {
+    name: String,
+    slug: String,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_lists(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.name,
+            l.slug,
+            COUNT(*) FILTER (WHERE s.status = 'confirmed') AS "n_confirmed!",
+            COUNT(*) FILTER (WHERE s.status = 'pending_confirmation') AS "n_pending!"
+        FROM lists l
+        LEFT JOIN list_subscriptions s USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists")?;
+    Ok(lists)
+}
@@ -0,0 +1,79 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+pub async fn create_list(
+    _: RequireRole<Editors>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    let slug = form.0.slug.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    if !is_valid_slug(&slug) {
+        FlashMessage::error("The slug must be 1 to 50 lowercase letters, digits or dashes.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    if insert_list(&pool, &name, &slug).await.map_err(e500)? {
+        FlashMessage::info(format!("The list `{}` has been created.", slug)).send();
+    } else {
+        FlashMessage::error(format!("There is already a list named `{}`.", slug)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 50
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Returns `false` if the slug is already taken.
+#[tracing::instrument(skip(pool))]
+async fn insert_list(pool: &PgPool, name: &str, slug: &str) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create the list")?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_slug;
+
+    #[test]
+    fn slugs_are_lowercase_alphanumerics_and_dashes() {
+        assert!(is_valid_slug("release-notes-2022"));
+        assert!(!is_valid_slug(""));
+        assert!(!is_valid_slug("Release"));
+        assert!(!is_valid_slug("release notes"));
+        assert!(!is_valid_slug(&"a".repeat(51)));
+    }
+}
@@ -1,8 +1,19 @@
 mod dashboard;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod security;
+mod subscribers;
+mod tokens;
+mod users;
 
 pub use dashboard::admin_dashboard;
+pub use lists::*;
 pub use logout::logout;
+pub use newsletter::*;
 pub use password::*;
+pub use security::*;
+pub use subscribers::*;
+pub use tokens::*;
+pub use users::*;
@@ -0,0 +1,66 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Cancel a scheduled issue, dropping its pending deliveries.
+/// Issues that are already going out can no longer be cancelled.
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(pool))]
+pub async fn cancel_newsletter_issue(
+    _: RequireRole<Editors>,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+
+    if try_cancel_issue(&pool, issue_id).await.map_err(e500)? {
+        FlashMessage::info("The newsletter issue has been cancelled.").send();
+    } else {
+        FlashMessage::error(
+            "Only scheduled issues that have not started going out can be cancelled.",
+        )
+        .send();
+    }
+
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+/// Returns `false` if the issue is not scheduled in the future.
+async fn try_cancel_issue(pool: &PgPool, issue_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET cancelled_at = now()
+        WHERE newsletter_issue_id = $1 AND publish_at > now() AND cancelled_at IS NULL
+        "#,
+        issue_id
+    )
+    .execute(&mut tx)
+    .await
+    .context("Failed to mark the newsletter issue as cancelled.")?
+    .rows_affected()
+        > 0;
+
+    if cancelled {
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1"#,
+            issue_id
+        )
+        .execute(&mut tx)
+        .await
+        .context("Failed to remove the pending deliveries of a cancelled issue.")?;
+    }
+
+    tx.commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue.")?;
+
+    Ok(cancelled)
+}
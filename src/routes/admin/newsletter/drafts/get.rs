@@ -0,0 +1,233 @@
+use super::{get_draft, Draft};
+use crate::mailing_lists::DEFAULT_LIST_SLUG;
+use crate::utils::e500;
+use actix_web::http::header::{ContentType, CONTENT_SECURITY_POLICY};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn list_drafts(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut drafts_html = String::new();
+    for (draft_id, title) in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a></li>"#,
+            draft_id,
+            htmlescape::encode_minimal(&title)
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Drafts</h1>
+    <ul>
+        {drafts_html}
+    </ul>
+    <h2>New draft</h2>
+    <form action="/admin/newsletters/drafts" method="post">
+        <label>Title
+            <input type="text" placeholder="Enter the issue title" name="title">
+        </label>
+        <br>
+        <label>Markdown content (when set, the HTML and plain text contents are generated from it)
+            <textarea placeholder="Enter the content in Markdown" name="markdown_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Plain text content
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <button type="submit">Save draft</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let Draft {
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+    } = match get_draft(&pool, draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let title = htmlescape::encode_attribute(&title);
+    let text_content = htmlescape::encode_minimal(&text_content);
+    let html_content = htmlescape::encode_minimal(&html_content);
+    let markdown_content = htmlescape::encode_minimal(&markdown_content.unwrap_or_default());
+    let idempotency_key = Uuid::new_v4();
+    let default_list = DEFAULT_LIST_SLUG;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/newsletters/drafts/{draft_id}" method="post">
+        <label>Title
+            <input type="text" name="title" value="{title}">
+        </label>
+        <br>
+        <label>Markdown content (when set, the HTML and plain text contents are generated from it)
+            <textarea name="markdown_content" rows="20" cols="50">{markdown_content}</textarea>
+        </label>
+        <br>
+        <label>Plain text content
+            <textarea name="text_content" rows="20" cols="50">{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea name="html_content" rows="20" cols="50">{html_content}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save draft</button>
+    </form>
+    <h2>Preview</h2>
+    <iframe sandbox src="/admin/newsletters/drafts/{draft_id}/preview" width="600" height="400"></iframe>
+    <p><a href="/admin/newsletters/drafts/{draft_id}/preview?format=text">Plain text version</a></p>
+    <form action="/admin/newsletters/drafts/{draft_id}/test" method="post">
+        <button type="submit">Send test to me</button>
+    </form>
+    <h2>Publish</h2>
+    <form action="/admin/newsletters/drafts/{draft_id}/publish" method="post">
+        <label>Publish at (RFC 3339, leave empty to send now)
+            <input type="text" placeholder="2022-07-01T09:00:00Z" name="publish_at">
+        </label>
+        <br>
+        <label>Lists (comma-separated slugs)
+            <input type="text" name="lists" value="{default_list}">
+        </label>
+        <br>
+        <label>Segment (e.g. <code>tag:vip -tag:churned attr.country:FR</code>, leave empty for everybody)
+            <input type="text" name="segment">
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="private" value="true">
+            Private (not listed in the public archive)
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="tracking" value="true">
+            Track opens and clicks
+        </label>
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    <form action="/admin/newsletters/drafts/{draft_id}/delete" method="post">
+        <button type="submit">Delete draft</button>
+    </form>
+    <p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    Html,
+    Text,
+}
+
+impl Default for PreviewFormat {
+    fn default() -> Self {
+        Self::Html
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewParameters {
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+/// Render the draft body on its own, as the subscribers would see it.
+///
+/// The HTML is sandboxed: it comes from editors and must not run scripts with
+/// the session of whoever opens the preview.
+pub async fn preview_draft(
+    draft_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let resp = match parameters.format {
+        PreviewFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .insert_header((CONTENT_SECURITY_POLICY, "sandbox"))
+            .body(draft.html_content),
+        PreviewFormat::Text => HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(draft.text_content),
+    };
+
+    Ok(resp)
+}
+
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<(Uuid, String)>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT newsletter_issue_id, title FROM newsletter_issues WHERE status = 'draft' ORDER BY title"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the newsletter drafts.")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.newsletter_issue_id, r.title))
+        .collect())
+}
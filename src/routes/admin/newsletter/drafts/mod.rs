@@ -0,0 +1,30 @@
+mod get;
+mod post;
+
+pub use get::*;
+pub use post::*;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Draft {
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+#[tracing::instrument(name = "Get a newsletter draft", skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"SELECT title, text_content, html_content, markdown_content FROM newsletter_issues WHERE newsletter_issue_id = $1 AND status = 'draft'"#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a newsletter draft.")?;
+
+    Ok(draft)
+}
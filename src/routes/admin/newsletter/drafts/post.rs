@@ -0,0 +1,317 @@
+use super::super::post::{
+    enqueue_delievery_tasks, parse_publish_at, stored_segment, success_message,
+};
+use super::get_draft;
+use crate::authentication::{Editors, RequireRole, UserID};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::mailing_lists::{get_list_ids, list_slugs_or_default, set_issue_lists};
+use crate::markdown::IssueContent;
+use crate::routes::issue_slug;
+use crate::segment::Segment;
+use crate::startup::ApplicationBaseUrl;
+use crate::templating::{IssueTemplate, MergeFields};
+use crate::utils::{e400, e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+impl DraftFormData {
+    fn content(self) -> (String, IssueContent) {
+        let content =
+            IssueContent::new(self.markdown_content, self.html_content, self.text_content);
+        (self.title, content)
+    }
+}
+
+#[tracing::instrument(name = "Create a newsletter draft", skip_all)]
+pub async fn create_draft(
+    _: RequireRole<Editors>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = Uuid::new_v4();
+    let (title, content) = form.0.content();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, markdown_content, status) VALUES ($1, $2, $3, $4, $5, 'draft')"#,
+        draft_id,
+        title,
+        content.text,
+        content.html,
+        content.markdown
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the newsletter draft.")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "Update a newsletter draft", skip(form, pool))]
+pub async fn update_draft(
+    _: RequireRole<Editors>,
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let (title, content) = form.0.content();
+    let n_updated = sqlx::query!(
+        r#"UPDATE newsletter_issues SET title = $2, text_content = $3, html_content = $4, markdown_content = $5 WHERE newsletter_issue_id = $1 AND status = 'draft'"#,
+        draft_id,
+        title,
+        content.text,
+        content.html,
+        content.markdown
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the newsletter draft.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_draft(
+    _: RequireRole<Editors>,
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1 AND status = 'draft'"#,
+        draft_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the newsletter draft.")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been deleted.").send();
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+/// Mail the draft to the logged-in user only, so they can check how it renders.
+/// Merge fields are filled in with sample values.
+#[tracing::instrument(
+    name = "Send a test email for a newsletter draft",
+    skip(pool, email_client, base_url, user_id),
+    fields(user_id=%&*user_id)
+)]
+pub async fn send_test_email(
+    _: RequireRole<Editors>,
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let draft = match get_draft(&pool, draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let location = format!("/admin/newsletters/drafts/{}", draft_id);
+
+    let recipient = match get_user_email(&pool, **user_id).await.map_err(e500)? {
+        Some(email) => SubscriberEmail::parse(email).map_err(|e| anyhow::anyhow!(e)),
+        None => Err(anyhow::anyhow!("The user has no email address.")),
+    };
+    let recipient = match recipient {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Unable to send a test email.");
+            FlashMessage::error("Your account has no valid email address to send a test to.")
+                .send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    let template = match IssueTemplate::parse(&draft.html_content, &draft.text_content) {
+        Ok(template) => template,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let (html_content, text_content) = template.render(&MergeFields {
+        name: "Test Subscriber",
+        email: recipient.as_ref(),
+        unsubscribe_url: &format!("{}/subscriptions/unsubscribe", base_url.0),
+        confirmed_at: Some(Utc::now()),
+    });
+
+    email_client
+        .send_email(
+            &recipient,
+            &format!("[Test] {}", draft.title),
+            &html_content,
+            &text_content,
+        )
+        .await
+        .context("Failed to send a test email.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("A test email has been sent to {}.", recipient)).send();
+    Ok(see_other(&location))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishDraftFormData {
+    idempotency_key: String,
+    publish_at: Option<String>,
+    lists: Option<String>,
+    segment: Option<String>,
+    #[serde(default)]
+    private: bool,
+    #[serde(default)]
+    tracking: bool,
+}
+
+/// Promote a draft to a real issue, going through the same idempotent
+/// publishing steps as a newly written issue.
+#[tracing::instrument(
+    name = "Publish a newsletter draft",
+    skip(form, pool, user_id),
+    fields(user_id=%&*user_id)
+)]
+pub async fn publish_draft(
+    _: RequireRole<Editors>,
+    draft_id: web::Path<Uuid>,
+    form: web::Form<PublishDraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let user_id = user_id.into_inner();
+    let PublishDraftFormData {
+        idempotency_key,
+        publish_at,
+        lists,
+        segment,
+        private,
+        tracking,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let publish_at = parse_publish_at(publish_at.as_deref()).map_err(e400)?;
+    let list_slugs = list_slugs_or_default(lists.as_deref());
+    let segment = Segment::parse(segment.as_deref().unwrap_or_default()).map_err(e400)?;
+
+    let mut tx = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_resp) => {
+            success_message(publish_at).send();
+            return Ok(saved_resp);
+        }
+    };
+
+    let list_ids = get_list_ids(&mut tx, &list_slugs).await.map_err(e400)?;
+
+    let content = promote_draft(&mut tx, draft_id, publish_at, &segment, private, tracking)
+        .await
+        .context("Failed to promote the newsletter draft")
+        .map_err(e500)?;
+    let (html_content, text_content) = match content {
+        Some(content) => content,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    // Dropping the transaction keeps the draft as it was.
+    IssueTemplate::parse(&html_content, &text_content).map_err(e400)?;
+
+    set_issue_lists(&mut tx, draft_id, &list_ids)
+        .await
+        .context("Failed to target the newsletter issue")
+        .map_err(e500)?;
+
+    enqueue_delievery_tasks(&mut tx, draft_id, &segment)
+        .await
+        .context("Failed to enqueue delivery tasks")
+        .map_err(e500)?;
+
+    let resp = see_other(&format!("/admin/newsletters/{}", draft_id));
+
+    let resp = save_response(tx, &idempotency_key, *user_id, resp)
+        .await
+        .map_err(e500)?;
+
+    success_message(publish_at).send();
+    Ok(resp)
+}
+
+/// Returns the `(html, text)` content of the promoted draft, `None` if there
+/// is no draft with the given id.
+#[tracing::instrument(skip(transaction, segment))]
+async fn promote_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    publish_at: DateTime<Utc>,
+    segment: &Segment,
+    private: bool,
+    tracking: bool,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let promoted = sqlx::query!(
+        r#"UPDATE newsletter_issues SET status = 'published', published_at = now(), publish_at = $2, segment = $3, private = $4, tracking = $5 WHERE newsletter_issue_id = $1 AND status = 'draft' RETURNING title, html_content, text_content"#,
+        draft_id,
+        publish_at,
+        stored_segment(segment),
+        private,
+        tracking
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let promoted = match promoted {
+        Some(promoted) => promoted,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET slug = $2 WHERE newsletter_issue_id = $1"#,
+        draft_id,
+        issue_slug(&promoted.title, draft_id)
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(Some((promoted.html_content, promoted.text_content)))
+}
+
+#[tracing::instrument(name = "Get the email address of a user", skip(pool))]
+async fn get_user_email(pool: &PgPool, user_id: Uuid) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the email address of a user.")?;
+
+    Ok(row.email)
+}
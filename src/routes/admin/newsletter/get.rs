@@ -1,5 +1,77 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::mailing_lists::DEFAULT_LIST_SLUG;
+use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+use uuid::Uuid;
 
-pub async fn publish_newlstter_form() -> Result<HttpResponse, actix_web::Error> {
-    todo!()
+pub async fn publish_newsletter_form(
+    _: RequireRole<Editors>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let idempotency_key = Uuid::new_v4();
+    let default_list = DEFAULT_LIST_SLUG;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Publish Newsletter Issue</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/newsletter" method="post">
+        <label>Title
+            <input type="text" placeholder="Enter the issue title" name="title">
+        </label>
+        <br>
+        <label>Markdown content (when set, the HTML and plain text contents are generated from it)
+            <textarea placeholder="Enter the content in Markdown" name="markdown_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Plain text content
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Publish at (RFC 3339, leave empty to send now)
+            <input type="text" placeholder="2022-07-01T09:00:00Z" name="publish_at">
+        </label>
+        <br>
+        <label>Lists (comma-separated slugs)
+            <input type="text" name="lists" value="{default_list}">
+        </label>
+        <br>
+        <label>Segment (e.g. <code>tag:vip -tag:churned attr.country:FR</code>, leave empty for everybody)
+            <input type="text" name="segment">
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="private" value="true">
+            Private (not listed in the public archive)
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="tracking" value="true">
+            Track opens and clicks
+        </label>
+        <br>
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
 }
@@ -1,2 +1,14 @@
+mod cancel;
+mod drafts;
 mod get;
 mod post;
+mod status;
+mod tracking;
+
+pub use cancel::cancel_newsletter_issue;
+pub use drafts::*;
+pub use get::publish_newsletter_form;
+pub use post::publish_newsletter_issue;
+pub(crate) use post::{enqueue_delievery_tasks, insert_newsletter_issue, parse_publish_at};
+pub use status::newsletter_issue_status;
+pub use tracking::set_issue_tracking;
@@ -1,20 +1,41 @@
-use crate::authentication::UserID;
+use crate::authentication::{Editors, RequireRole, UserID};
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::mailing_lists::{get_list_ids, list_slugs_or_default, set_issue_lists};
+use crate::markdown::IssueContent;
+use crate::routes::issue_slug;
+use crate::segment::Segment;
+use crate::templating::IssueTemplate;
 use crate::utils::{e400, e500, see_other};
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
+    /// Generates the two contents above when set.
+    markdown_content: Option<String>,
     idempotency_key: String,
+    /// RFC 3339 timestamp at which the issue should go out. Empty to send it now.
+    publish_at: Option<String>,
+    /// Comma-separated slugs of the targeted lists.
+    lists: Option<String>,
+    /// Only send the issue to the subscribers matching this segment.
+    segment: Option<String>,
+    /// Leave the issue out of the public archive.
+    #[serde(default)]
+    private: bool,
+    /// Track the opens and clicks of the issue.
+    #[serde(default)]
+    tracking: bool,
 }
 
 #[tracing::instrument(
@@ -22,8 +43,9 @@ pub struct FormData {
     skip_all,
     fields(user_id=%&*user_id)
 )]
-pub async fn publish_newsletter(
-    form: web::Json<FormData>,
+pub async fn publish_newsletter_issue(
+    _: RequireRole<Editors>,
+    form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: ReqData<UserID>,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -32,9 +54,20 @@ pub async fn publish_newsletter(
         title,
         text_content,
         html_content,
+        markdown_content,
         idempotency_key,
+        publish_at,
+        lists,
+        segment,
+        private,
+        tracking,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let publish_at = parse_publish_at(publish_at.as_deref()).map_err(e400)?;
+    let list_slugs = list_slugs_or_default(lists.as_deref());
+    let segment = Segment::parse(segment.as_deref().unwrap_or_default()).map_err(e400)?;
+    let content = IssueContent::new(markdown_content, html_content, text_content);
+    IssueTemplate::parse(&content.html, &content.text).map_err(e400)?;
 
     let mut tx = match try_processing(&pool, &idempotency_key, *user_id)
         .await
@@ -42,17 +75,26 @@ pub async fn publish_newsletter(
     {
         NextAction::StartProcessing(t) => t,
         NextAction::ReturnSavedResponse(saved_resp) => {
-            success_message().send();
+            success_message(publish_at).send();
             return Ok(saved_resp);
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut tx, &title, &text_content, &html_content)
+    let list_ids = get_list_ids(&mut tx, &list_slugs).await.map_err(e400)?;
+
+    let issue_id = insert_newsletter_issue(
+        &mut tx, &title, &content, publish_at, &segment, private, tracking,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+
+    set_issue_lists(&mut tx, issue_id, &list_ids)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to target the newsletter issue")
         .map_err(e500)?;
 
-    enqueue_delievery_tasks(&mut tx, issue_id)
+    enqueue_delievery_tasks(&mut tx, issue_id, &segment)
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
@@ -63,70 +105,136 @@ pub async fn publish_newsletter(
         .await
         .map_err(e500)?;
 
-    success_message().send();
+    success_message(publish_at).send();
     Ok(resp)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issus has been accepted - emails will go out shortly.")
-}
-
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+/// A missing or empty `publish_at` means "right now".
+pub(crate) fn parse_publish_at(publish_at: Option<&str>) -> Result<DateTime<Utc>, anyhow::Error> {
+    match publish_at.map(str::trim) {
+        None | Some("") => Ok(Utc::now()),
+        Some(publish_at) => {
+            let publish_at = DateTime::parse_from_rfc3339(publish_at)
+                .with_context(|| format!("`{}` is not a valid RFC 3339 timestamp.", publish_at))?;
+            Ok(publish_at.with_timezone(&Utc))
+        }
+    }
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    struct Row {
-        email: String,
+pub(super) fn success_message(publish_at: DateTime<Utc>) -> FlashMessage {
+    if publish_at > Utc::now() {
+        FlashMessage::info(format!(
+            "The newsletter issue has been scheduled - emails will go out at {}.",
+            publish_at.to_rfc3339()
+        ))
+    } else {
+        FlashMessage::info("The newsletter issus has been accepted - emails will go out shortly.")
     }
-
-    let rows = sqlx::query_as!(
-        Row,
-        r#"
-        SELECT email FROM subscriptions WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
-
-    let confirmed_subscribers = rows
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
-            Err(error) => Err(anyhow::anyhow!(error)),
-        })
-        .collect();
-
-    Ok(confirmed_subscribers)
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
+    publish_at: DateTime<Utc>,
+    segment: &Segment,
+    private: bool,
+    tracking: bool,
 ) -> Result<Uuid, sqlx::Error> {
     let issue_id = Uuid::new_v4();
 
-    sqlx::query!(r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at) VALUES ($1, $2, $3, $4, now())"#,
+    sqlx::query!(r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, markdown_content, published_at, publish_at, segment, slug, private, tracking) VALUES ($1, $2, $3, $4, $5, now(), $6, $7, $8, $9, $10)"#,
     issue_id,
     title,
-    text_content,
-    html_content,
+    content.text,
+    content.html,
+    content.markdown,
+    publish_at,
+    stored_segment(segment),
+    issue_slug(title, issue_id),
+    private,
+    tracking,
     ).execute(transaction).await?;
 
     Ok(issue_id)
 }
 
+/// The canonical form of the segment, `None` when the issue goes to everybody.
+pub(crate) fn stored_segment(segment: &Segment) -> Option<String> {
+    if segment.is_empty() {
+        None
+    } else {
+        Some(segment.to_string())
+    }
+}
+
+/// Queue one delivery per subscriber confirmed on any of the issue's lists
+/// and matching the segment.
 #[tracing::instrument(skip_all)]
-async fn enqueue_delievery_tasks(
+pub(crate) async fn enqueue_delievery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     issue_id: Uuid,
+    segment: &Segment,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) SELECT $1, email FROM subscriptions WHERE status = 'confirmed'"#, issue_id).execute(transaction).await?;
+    let (attribute_names, attribute_values) = segment.attribute_arrays();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT DISTINCT $1::uuid, s.email
+        FROM subscriptions s
+        JOIN list_subscriptions l ON l.subscriber_id = s.id
+        JOIN newsletter_issue_lists il ON il.list_id = l.list_id
+        WHERE il.newsletter_issue_id = $1 AND l.status = 'confirmed' AND s.status = 'confirmed'
+          AND $2::text[] <@ ARRAY(SELECT tag FROM subscriber_tags t WHERE t.subscriber_id = s.id)
+          AND NOT ($3::text[] && ARRAY(SELECT tag FROM subscriber_tags t WHERE t.subscriber_id = s.id))
+          AND ($4::timestamptz IS NULL OR s.subscribed_at >= $4)
+          AND ($5::timestamptz IS NULL OR s.subscribed_at < $5)
+          AND NOT EXISTS (
+              SELECT 1 FROM UNNEST($6::text[], $7::text[]) AS a(name, value)
+              WHERE NOT EXISTS (
+                  SELECT 1 FROM subscriber_attributes sa
+                  WHERE sa.subscriber_id = s.id AND sa.name = a.name AND sa.value = a.value
+              )
+          )
+        "#,
+        issue_id,
+        &segment.tags,
+        &segment.excluded_tags,
+        segment.subscribed_from,
+        segment.subscribed_before,
+        &attribute_names,
+        &attribute_values,
+    )
+    .execute(transaction)
+    .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_publish_at;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_missing_or_empty_publish_at_means_now() {
+        for publish_at in [None, Some(""), Some("  ")] {
+            let parsed = parse_publish_at(publish_at).unwrap();
+            assert!((Utc::now() - parsed).num_seconds() < 5);
+        }
+    }
+
+    #[test]
+    fn rfc_3339_timestamps_are_converted_to_utc() {
+        let parsed = parse_publish_at(Some("2022-07-01T11:00:00+02:00"));
+
+        assert_ok!(&parsed);
+        assert_eq!(parsed.unwrap(), Utc.ymd(2022, 7, 1).and_hms(9, 0, 0));
+    }
+
+    #[test]
+    fn timestamps_without_an_offset_are_rejected() {
+        assert_err!(parse_publish_at(Some("2022-07-01T09:00")));
+    }
+}
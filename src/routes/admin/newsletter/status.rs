@@ -0,0 +1,279 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DeliveryStatus {
+    title: String,
+    published_at: Option<DateTime<Utc>>,
+    publish_at: DateTime<Utc>,
+    cancelled_at: Option<DateTime<Utc>>,
+    segment: Option<String>,
+    slug: Option<String>,
+    private: bool,
+    tracking: bool,
+    n_sent: i64,
+    n_failed: i64,
+    n_pending: i64,
+}
+
+pub async fn newsletter_issue_status(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = match get_delivery_status(&pool, *issue_id).await.map_err(e500)? {
+        Some(status) => status,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let DeliveryStatus {
+        title,
+        published_at,
+        publish_at,
+        cancelled_at,
+        segment,
+        slug,
+        private,
+        tracking,
+        n_sent,
+        n_failed,
+        n_pending,
+    } = status;
+    let title = htmlescape::encode_minimal(&title);
+
+    let segment_html = match segment {
+        Some(segment) => format!(
+            "<p>Segment: <code>{}</code></p>",
+            htmlescape::encode_minimal(&segment)
+        ),
+        None => String::new(),
+    };
+
+    let archive_html = match slug {
+        _ if private => "<p>This issue is private: it is not listed in the archive.</p>".to_owned(),
+        Some(slug) => format!(
+            r#"<p>Web version: <a href="/archive/{0}">/archive/{0}</a></p>"#,
+            htmlescape::encode_minimal(&slug)
+        ),
+        None => String::new(),
+    };
+
+    let engagement = get_engagement(&pool, *issue_id).await.map_err(e500)?;
+    let engagement_html = engagement_html(*issue_id, tracking, n_sent, &engagement);
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let schedule_html = match (published_at, cancelled_at) {
+        (None, _) => format!(
+            r#"<p>This issue is still a <a href="/admin/newsletters/drafts/{}">draft</a>.</p>"#,
+            issue_id
+        ),
+        (Some(published_at), Some(cancelled_at)) => format!(
+            "<p>Published at {}</p>\n    <p>Cancelled at {}</p>",
+            published_at.to_rfc3339(),
+            cancelled_at.to_rfc3339()
+        ),
+        (Some(published_at), None) if publish_at > Utc::now() => format!(
+            r#"<p>Published at {}</p>
+    <p>Scheduled for {}</p>
+    <form action="/admin/newsletters/{}/cancel" method="post">
+        <button type="submit">Cancel this issue</button>
+    </form>"#,
+            published_at.to_rfc3339(),
+            publish_at.to_rfc3339(),
+            issue_id
+        ),
+        (Some(published_at), None) => format!(
+            "<p>Published at {}</p>\n    <p>Sent out from {}</p>",
+            published_at.to_rfc3339(),
+            publish_at.to_rfc3339()
+        ),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issue</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    {schedule_html}
+    {segment_html}
+    {archive_html}
+    <ul>
+        <li>Sent: {n_sent}</li>
+        <li>Failed: {n_failed}</li>
+        <li>Pending: {n_pending}</li>
+    </ul>
+    {engagement_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get newsletter issue delivery status", skip(pool))]
+async fn get_delivery_status(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryStatus>, anyhow::Error> {
+    let status = sqlx::query_as!(
+        DeliveryStatus,
+        r#"
+        SELECT
+            title,
+            published_at,
+            publish_at,
+            cancelled_at,
+            segment,
+            slug,
+            private,
+            tracking,
+            (SELECT count(*) FROM newsletter_deliveries d WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'sent') as "n_sent!",
+            (SELECT count(*) FROM newsletter_deliveries d WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'failed') as "n_failed!",
+            (SELECT count(*) FROM issue_delivery_queue q WHERE q.newsletter_issue_id = i.newsletter_issue_id) as "n_pending!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Unable to perform a query to retrieve the delivery status of an issue.")?;
+
+    Ok(status)
+}
+
+struct Engagement {
+    n_opened: i64,
+    n_opens: i64,
+    n_clicked: i64,
+    n_clicks: i64,
+    links: Vec<LinkClicks>,
+}
+
+struct LinkClicks {
+    url: String,
+    n_clicked: i64,
+    n_clicks: i64,
+}
+
+fn engagement_html(issue_id: Uuid, tracking: bool, n_sent: i64, engagement: &Engagement) -> String {
+    let (state, toggle_value, toggle_label) = if tracking {
+        (
+            "Opens and clicks are tracked for this issue.",
+            false,
+            "Stop tracking",
+        )
+    } else {
+        (
+            "Opens and clicks are not tracked for this issue.",
+            true,
+            "Start tracking",
+        )
+    };
+
+    let mut links_html = String::new();
+    for link in &engagement.links {
+        writeln!(
+            links_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&link.url),
+            link.n_clicked,
+            link.n_clicks
+        )
+        .unwrap();
+    }
+    if !links_html.is_empty() {
+        links_html = format!(
+            "<table>\n<tr><th>Link</th><th>Clicked by</th><th>Clicks</th></tr>\n{}</table>",
+            links_html
+        );
+    }
+
+    format!(
+        r#"<h2>Engagement</h2>
+    <p>{state}</p>
+    <form action="/admin/newsletters/{issue_id}/tracking" method="post">
+        <input hidden type="text" name="tracking" value="{toggle_value}">
+        <button type="submit">{toggle_label}</button>
+    </form>
+    <ul>
+        <li>Opened by: {n_opened}{open_rate}</li>
+        <li>Opens: {n_opens}</li>
+        <li>Clicked by: {n_clicked}{click_rate}</li>
+        <li>Clicks: {n_clicks}</li>
+    </ul>
+    {links_html}"#,
+        n_opened = engagement.n_opened,
+        open_rate = rate(engagement.n_opened, n_sent),
+        n_opens = engagement.n_opens,
+        n_clicked = engagement.n_clicked,
+        click_rate = rate(engagement.n_clicked, n_sent),
+        n_clicks = engagement.n_clicks,
+    )
+}
+
+/// The share of the sent emails, e.g. ` (42.5%)`.
+fn rate(n: i64, n_sent: i64) -> String {
+    if n_sent == 0 {
+        String::new()
+    } else {
+        format!(" ({:.1}%)", n as f64 * 100.0 / n_sent as f64)
+    }
+}
+
+#[tracing::instrument(name = "Get newsletter issue engagement", skip(pool))]
+async fn get_engagement(pool: &PgPool, issue_id: Uuid) -> Result<Engagement, anyhow::Error> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            count(DISTINCT subscriber_id) FILTER (WHERE kind = 'open') as "n_opened!",
+            count(*) FILTER (WHERE kind = 'open') as "n_opens!",
+            count(DISTINCT subscriber_id) FILTER (WHERE kind = 'click') as "n_clicked!",
+            count(*) FILTER (WHERE kind = 'click') as "n_clicks!"
+        FROM issue_events
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the tracking events of an issue.")?;
+
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT url as "url!", count(DISTINCT subscriber_id) as "n_clicked!", count(*) as "n_clicks!"
+        FROM issue_events
+        WHERE newsletter_issue_id = $1 AND kind = 'click' AND url IS NOT NULL
+        GROUP BY url
+        ORDER BY count(*) DESC, url
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the clicks per link of an issue.")?;
+
+    Ok(Engagement {
+        n_opened: totals.n_opened,
+        n_opens: totals.n_opens,
+        n_clicked: totals.n_clicked,
+        n_clicks: totals.n_clicks,
+        links,
+    })
+}
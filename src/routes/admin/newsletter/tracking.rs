@@ -0,0 +1,48 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct TrackingFormData {
+    tracking: bool,
+}
+
+/// Turn open and click tracking on or off for an issue. Emails that have
+/// already gone out keep their tracked links, but their events are no longer
+/// recorded once tracking is off.
+#[tracing::instrument(name = "Toggle the tracking of a newsletter issue", skip(form, pool))]
+pub async fn set_issue_tracking(
+    _: RequireRole<Editors>,
+    issue_id: web::Path<Uuid>,
+    form: web::Form<TrackingFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let tracking = form.0.tracking;
+
+    let updated = sqlx::query!(
+        r#"UPDATE newsletter_issues SET tracking = $2 WHERE newsletter_issue_id = $1"#,
+        issue_id,
+        tracking
+    )
+    .execute(&**pool)
+    .await
+    .context("Failed to update the tracking of a newsletter issue.")
+    .map_err(e500)?
+    .rows_affected()
+        > 0;
+    if !updated {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    if tracking {
+        FlashMessage::info("Opens and clicks are now tracked for this issue.").send();
+    } else {
+        FlashMessage::info("Opens and clicks are no longer tracked for this issue.").send();
+    }
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
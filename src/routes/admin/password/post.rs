@@ -49,7 +49,7 @@ pub async fn change_password(
         };
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    crate::authentication::change_password(*user_id, form.0.new_password, pool.get_ref())
         .await
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
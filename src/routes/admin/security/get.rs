@@ -0,0 +1,90 @@
+use crate::authentication::{get_totp_status, provisioning_uri, TotpKey, TotpStatus, UserID};
+use crate::routes::admin::dashboard::get_username;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn security_settings(
+    pool: web::Data<PgPool>,
+    totp_key: web::Data<TotpKey>,
+    user_id: ReqData<UserID>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let totp_html = match get_totp_status(&pool, &totp_key, **user_id)
+        .await
+        .map_err(e500)?
+    {
+        TotpStatus::Disabled => r#"<p>Two-factor authentication is off.</p>
+    <form action="/admin/security/totp" method="post">
+        <button type="submit">Set up an authenticator app</button>
+    </form>"#
+            .to_string(),
+        TotpStatus::Enrolling(secret) => {
+            let username = get_username(**user_id, &pool).await.map_err(e500)?;
+            let uri = provisioning_uri(&username, &secret);
+            let qr_code = QrCode::new(uri.as_bytes())
+                .map_err(e500)?
+                .render::<svg::Color>()
+                .min_dimensions(200, 200)
+                .build();
+            format!(
+                r#"<p>Scan this QR code with your authenticator app:</p>
+    {qr_code}
+    <p>Or enter this key by hand: <code id="totp-secret">{secret}</code></p>
+    <p><code id="totp-uri">{uri}</code></p>
+    <form action="/admin/security/totp/confirm" method="post">
+        <label>Then enter the code it shows
+            <input type="text" autocomplete="one-time-code" name="code">
+        </label>
+        <button type="submit">Turn on</button>
+    </form>
+    <form action="/admin/security/totp" method="post">
+        <button type="submit">Start over with a new key</button>
+    </form>"#,
+                secret = secret.expose_secret(),
+                uri = htmlescape::encode_minimal(&uri),
+            )
+        }
+        TotpStatus::Enabled {
+            recovery_codes_left,
+        } => format!(
+            r#"<p>Two-factor authentication is on. You have {recovery_codes_left} recovery codes left.</p>
+    <form action="/admin/security/totp/disable" method="post">
+        <label>Enter a code to turn it off
+            <input type="text" autocomplete="one-time-code" name="code">
+        </label>
+        <button type="submit">Turn off</button>
+    </form>"#
+        ),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Security</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Two-factor authentication</h1>
+    {totp_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::security_settings;
+pub use post::{confirm_totp, remove_totp, set_up_totp};
@@ -0,0 +1,99 @@
+use crate::authentication::{
+    begin_totp_enrolment, disable_totp, enable_totp, verify_second_factor, SecondFactor, TotpKey,
+    UserID,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct CodeFormData {
+    code: Secret<String>,
+}
+
+/// Generate a new TOTP secret, replacing any enrolment in progress.
+pub async fn set_up_totp(
+    pool: web::Data<PgPool>,
+    totp_key: web::Data<TotpKey>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !begin_totp_enrolment(&pool, &totp_key, **user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Two-factor authentication is already on.").send();
+    }
+    Ok(see_other("/admin/security"))
+}
+
+/// Turn TOTP on and show the recovery codes, once: only their hashes are
+/// kept.
+pub async fn confirm_totp(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    totp_key: web::Data<TotpKey>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recovery_codes = match enable_totp(&pool, &totp_key, **user_id, form.code.expose_secret())
+        .await
+        .map_err(e500)?
+    {
+        Some(recovery_codes) => recovery_codes,
+        None => {
+            FlashMessage::error("The code is incorrect.").send();
+            return Ok(see_other("/admin/security"));
+        }
+    };
+
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code.expose_secret()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Security</title>
+</head>
+<body>
+    <p>Two-factor authentication is on.</p>
+    <p>If you lose your authenticator app, you can log in with one of these
+    recovery codes instead. Each of them works once.</p>
+    <ul id="recovery-codes">
+{codes_html}    </ul>
+    <p>Copy them now, they will not be shown again.</p>
+    <p><a href="/admin/security">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn remove_totp(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    totp_key: web::Data<TotpKey>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match verify_second_factor(&pool, &totp_key, **user_id, form.code.expose_secret())
+        .await
+        .map_err(e500)?
+    {
+        SecondFactor::Accepted => {
+            disable_totp(&pool, **user_id).await.map_err(e500)?;
+            FlashMessage::info("Two-factor authentication is off.").send();
+        }
+        SecondFactor::Rejected => FlashMessage::error("The code is incorrect.").send(),
+        SecondFactor::LockedOut => {
+            FlashMessage::error("Too many wrong codes, please try again later.").send()
+        }
+    }
+    Ok(see_other("/admin/security"))
+}
@@ -0,0 +1,94 @@
+use crate::utils::e500;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Number of subscribers fetched from the database for each chunk of the export.
+const EXPORT_CHUNK_SIZE: i64 = 1000;
+
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+/// Stream every subscriber as CSV, one chunk at a time, so that the export
+/// never holds the whole list (or a connection) for the full response.
+pub async fn export_subscribers(pool: web::Data<PgPool>) -> HttpResponse {
+    let pool = pool.get_ref().clone();
+    let chunks = futures_util::stream::try_unfold(
+        Some((pool, None)),
+        |state: Option<(PgPool, Option<Uuid>)>| async move {
+            let (pool, after) = match state {
+                Some(state) => state,
+                None => return Ok(None),
+            };
+            let subscribers = fetch_chunk(&pool, after).await.map_err(e500)?;
+            let is_first = after.is_none();
+            let last_id = subscribers.last().map(|s| s.id);
+            let chunk = write_chunk(&subscribers, is_first).map_err(e500)?;
+            let next = match last_id {
+                Some(last_id) if subscribers.len() as i64 == EXPORT_CHUNK_SIZE => {
+                    Some((pool, Some(last_id)))
+                }
+                _ => None,
+            };
+            Ok::<_, actix_web::Error>(Some((web::Bytes::from(chunk), next)))
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+        })
+        .streaming(chunks)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn fetch_chunk(
+    pool: &PgPool,
+    after: Option<Uuid>,
+) -> Result<Vec<ExportedSubscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::uuid IS NULL OR id > $1
+        ORDER BY id
+        LIMIT $2
+        "#,
+        after,
+        EXPORT_CHUNK_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch subscribers to export")?;
+    Ok(subscribers)
+}
+
+fn write_chunk(
+    subscribers: &[ExportedSubscriber],
+    with_header: bool,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if with_header {
+        writer.write_record(["email", "name", "status", "subscribed_at"])?;
+    }
+    for s in subscribers {
+        writer.write_record([
+            s.email.as_str(),
+            s.name.as_str(),
+            s.status.as_str(),
+            s.subscribed_at.to_rfc3339().as_str(),
+        ])?;
+    }
+    writer.into_inner().context("Failed to flush the CSV chunk")
+}
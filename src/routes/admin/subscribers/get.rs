@@ -0,0 +1,536 @@
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+/// Pages past this one are served as this one, so that the offset cannot
+/// overflow.
+const MAX_PAGE: i64 = 1_000_000;
+
+#[derive(serde::Deserialize)]
+pub struct SearchParameters {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    status: String,
+    /// First day (inclusive, `YYYY-MM-DD`) of the subscription date range.
+    #[serde(default)]
+    subscribed_from: String,
+    /// Last day (inclusive, `YYYY-MM-DD`) of the subscription date range.
+    #[serde(default)]
+    subscribed_to: String,
+    page: Option<i64>,
+}
+
+impl SearchParameters {
+    /// The query string for another page of the same search.
+    fn query_for_page(&self, page: i64) -> String {
+        format!(
+            "email={}&name={}&status={}&subscribed_from={}&subscribed_to={}&page={}",
+            urlencoding::encode(&self.email),
+            urlencoding::encode(&self.name),
+            urlencoding::encode(&self.status),
+            urlencoding::encode(&self.subscribed_from),
+            urlencoding::encode(&self.subscribed_to),
+            page
+        )
+    }
+}
+
+struct SubscriberFilter {
+    email: Option<String>,
+    name: Option<String>,
+    status: Option<String>,
+    subscribed_from: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<&SearchParameters> for SubscriberFilter {
+    type Error = String;
+
+    fn try_from(value: &SearchParameters) -> Result<Self, Self::Error> {
+        let subscribed_from = parse_day(&value.subscribed_from)?;
+        let subscribed_before = parse_day(&value.subscribed_to)?.map(|d| d + Duration::days(1));
+
+        Ok(Self {
+            email: contains_pattern(&value.email),
+            name: contains_pattern(&value.name),
+            status: Some(value.status.trim().to_owned()).filter(|s| !s.is_empty()),
+            subscribed_from,
+            subscribed_before,
+        })
+    }
+}
+
+fn parse_day(s: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let day = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("`{}` is not a valid date, expected YYYY-MM-DD.", s))?;
+    Ok(Some(DateTime::from_utc(day.and_hms(0, 0, 0), Utc)))
+}
+
+/// Build a case-insensitive "contains" `ILIKE` pattern, escaping the wildcards.
+fn contains_pattern(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
+
+pub async fn list_subscribers(
+    parameters: web::Query<SearchParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = SubscriberFilter::try_from(&parameters.0).map_err(e400)?;
+    let page = parameters.page.unwrap_or(1).clamp(1, MAX_PAGE);
+
+    let total = count_subscribers(&pool, &filter).await.map_err(e500)?;
+    let subscribers = search_subscribers(&pool, &filter, page)
+        .await
+        .map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for s in subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            s.id,
+            htmlescape::encode_minimal(&s.email),
+            htmlescape::encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}">Previous</a> "#,
+            htmlescape::encode_minimal(&parameters.query_for_page(page - 1))
+        )
+        .unwrap();
+    }
+    if page * PAGE_SIZE < total {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}">Next</a>"#,
+            htmlescape::encode_minimal(&parameters.query_for_page(page + 1))
+        )
+        .unwrap();
+    }
+
+    let status_options: String = [
+        "",
+        "pending_confirmation",
+        "confirmed",
+        "unsubscribed",
+        "suppressed",
+    ]
+    .iter()
+    .map(|status| {
+        let selected = if *status == parameters.status {
+            " selected"
+        } else {
+            ""
+        };
+        let label = if status.is_empty() { "any" } else { status };
+        format!(r#"<option value="{status}"{selected}>{label}</option>"#)
+    })
+    .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Subscribers</h1>
+    <p><a href="/admin/subscribers/import">Import from CSV</a> | <a href="/admin/subscribers/export">Export as CSV</a></p>
+    <form action="/admin/subscribers" method="get">
+        <label>Email <input type="text" name="email" value="{email}"></label>
+        <label>Name <input type="text" name="name" value="{name}"></label>
+        <label>Status <select name="status">{status_options}</select></label>
+        <label>Subscribed from <input type="date" name="subscribed_from" value="{subscribed_from}"></label>
+        <label>to <input type="date" name="subscribed_to" value="{subscribed_to}"></label>
+        <button type="submit">Search</button>
+    </form>
+    <p>{total} subscriber(s) found.</p>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows_html}
+    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = htmlescape::encode_attribute(&parameters.email),
+            name = htmlescape::encode_attribute(&parameters.name),
+            subscribed_from = htmlescape::encode_attribute(&parameters.subscribed_from),
+            subscribed_to = htmlescape::encode_attribute(&parameters.subscribed_to),
+        )))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+    for l in get_list_subscriptions(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            lists_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&l.name),
+            l.status,
+            l.subscribed_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+
+    let tags = get_tags(&pool, subscriber_id).await.map_err(e500)?;
+    let mut attributes = String::new();
+    for (name, value) in get_attributes(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(attributes, "{}={}", name, value).unwrap();
+    }
+
+    let mut deliveries_html = String::new();
+    for d in get_delivery_history(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            deliveries_html,
+            r#"<tr><td><a href="/admin/newsletters/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            d.newsletter_issue_id,
+            htmlescape::encode_minimal(&d.title),
+            d.outcome,
+            d.n_attempts,
+            d.last_attempted_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+
+    let mut email_events_html = String::new();
+    for e in get_email_events(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            email_events_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&e.record_type),
+            htmlescape::encode_minimal(&e.details.unwrap_or_default()),
+            e.received_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{email}</h1>
+    <p>Name: {name}</p>
+    <p>Status: {status}</p>
+    <p>Subscribed at: {subscribed_at}</p>
+    <form action="/admin/subscribers/{subscriber_id}/confirm" method="post">
+        <button type="submit">Confirm</button>
+    </form>
+    <form action="/admin/subscribers/{subscriber_id}/unsubscribe" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+    <form action="/admin/subscribers/{subscriber_id}/delete" method="post">
+        <button type="submit">Delete</button>
+    </form>
+    <h2>Tags and attributes</h2>
+    <form action="/admin/subscribers/{subscriber_id}/tags" method="post">
+        <label>Tags (comma-separated)
+            <input type="text" name="tags" value="{tags}">
+        </label>
+        <br>
+        <label>Attributes (one <code>name=value</code> per line)
+            <textarea name="attributes" rows="5" cols="50">{attributes}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <h2>Lists</h2>
+    <table>
+        <tr><th>List</th><th>Status</th><th>Subscribed at</th></tr>
+        {lists_html}
+    </table>
+    <h2>Delivery history</h2>
+    <table>
+        <tr><th>Issue</th><th>Outcome</th><th>Attempts</th><th>Last attempt</th></tr>
+        {deliveries_html}
+    </table>
+    <h2>Provider events</h2>
+    <table>
+        <tr><th>Event</th><th>Details</th><th>Received at</th></tr>
+        {email_events_html}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            name = htmlescape::encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+            tags = htmlescape::encode_minimal(&tags.join(", ")),
+            attributes = htmlescape::encode_minimal(&attributes),
+        )))
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct ListSubscription {
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    outcome: String,
+    n_attempts: i16,
+    last_attempted_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn count_subscribers(pool: &PgPool, filter: &SubscriberFilter) -> Result<i64, anyhow::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1)
+          AND ($2::text IS NULL OR name ILIKE $2)
+          AND ($3::text IS NULL OR status = $3)
+          AND ($4::timestamptz IS NULL OR subscribed_at >= $4)
+          AND ($5::timestamptz IS NULL OR subscribed_at < $5)
+        "#,
+        filter.email,
+        filter.name,
+        filter.status,
+        filter.subscribed_from,
+        filter.subscribed_before,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers")?;
+    Ok(record.count)
+}
+
+#[tracing::instrument(skip_all)]
+async fn search_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    page: i64,
+) -> Result<Vec<Subscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1)
+          AND ($2::text IS NULL OR name ILIKE $2)
+          AND ($3::text IS NULL OR status = $3)
+          AND ($4::timestamptz IS NULL OR subscribed_at >= $4)
+          AND ($5::timestamptz IS NULL OR subscribed_at < $5)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $6 OFFSET $7
+        "#,
+        filter.email,
+        filter.name,
+        filter.status,
+        filter.subscribed_from,
+        filter.subscribed_before,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to search subscribers")?;
+    Ok(subscribers)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_list_subscriptions(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListSubscription>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSubscription,
+        r#"
+        SELECT l.name, s.status, s.subscribed_at
+        FROM list_subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE s.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list subscriptions")?;
+    Ok(lists)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_tags(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<String>, anyhow::Error> {
+    let tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber tags")?;
+    Ok(tags.into_iter().map(|r| r.tag).collect())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_attributes(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let attributes = sqlx::query!(
+        "SELECT name, value FROM subscriber_attributes WHERE subscriber_id = $1 ORDER BY name",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber attributes")?;
+    Ok(attributes.into_iter().map(|r| (r.name, r.value)).collect())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_delivery_history(pool: &PgPool, email: &str) -> Result<Vec<Delivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.outcome, d.n_attempts, d.last_attempted_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.subscriber_email = $1
+        ORDER BY d.last_attempted_at DESC
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history")?;
+    Ok(deliveries)
+}
+
+struct EmailEvent {
+    record_type: String,
+    details: Option<String>,
+    received_at: DateTime<Utc>,
+}
+
+/// Bounces, spam complaints and deliveries reported for the address.
+#[tracing::instrument(skip(pool))]
+async fn get_email_events(pool: &PgPool, email: &str) -> Result<Vec<EmailEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT record_type, details, received_at
+        FROM email_events
+        WHERE email = $1
+        ORDER BY received_at DESC
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the email events")?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::contains_pattern;
+
+    #[test]
+    fn contains_pattern_escapes_like_wildcards() {
+        assert_eq!(contains_pattern("  "), None);
+        assert_eq!(contains_pattern("ursula").unwrap(), "%ursula%");
+        assert_eq!(contains_pattern("100%_a\\b").unwrap(), "%100\\%\\_a\\\\b%");
+    }
+}
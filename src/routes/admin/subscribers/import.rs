@@ -0,0 +1,379 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::mailing_lists::{add_to_list, get_list_id, ListError, DEFAULT_LIST_SLUG};
+use crate::routes::{
+    generate_subcription_token, insert_subscriber, send_confirmation_email, store_token,
+    InsertOutcome,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_multipart::Multipart;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Uploads above this size are refused before being parsed.
+const MAX_UPLOAD_SIZE: usize = 5 * 1024 * 1024;
+
+pub async fn import_subscribers_form(
+    _: RequireRole<Editors>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let default_list = DEFAULT_LIST_SLUG;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Import subscribers</h1>
+    <p>Upload a CSV file with a header row containing at least the <code>email</code> and <code>name</code> columns.</p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <input type="file" name="file" accept=".csv,text/csv">
+        <br>
+        <label><input type="radio" name="mode" value="send_confirmation" checked> Send a confirmation email</label>
+        <label><input type="radio" name="mode" value="confirmed"> Mark as confirmed</label>
+        <br>
+        <label>List <input type="text" name="list" value="{default_list}"></label>
+        <br>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ImportMode {
+    SendConfirmation,
+    Confirmed,
+}
+
+enum RowOutcome {
+    Imported,
+    Skipped(String),
+    Invalid(String),
+}
+
+struct ImportRow {
+    line: u64,
+    email: String,
+    outcome: RowOutcome,
+}
+
+#[tracing::instrument(name = "Import subscribers", skip_all)]
+pub async fn import_subscribers(
+    _: RequireRole<Editors>,
+    payload: Multipart,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Upload { file, mode, list } = match read_upload(payload).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+    let records = match parse_csv(&file) {
+        Ok(records) => records,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection for transaction")
+        .map_err(e500)?;
+    let list_id = match get_list_id(&mut transaction, &list).await {
+        Ok(list_id) => list_id,
+        Err(ListError::UnexpectedError(e)) => return Err(e500(e)),
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+    let mut rows = Vec::with_capacity(records.len());
+    let mut confirmations = Vec::new();
+    for (line, email, name) in records {
+        let outcome = match parse_row(email.clone(), name) {
+            Ok(subscriber) => {
+                let (outcome, token) = import_row(&mut transaction, &subscriber, list_id, mode)
+                    .await
+                    .map_err(e500)?;
+                if let Some(token) = token {
+                    confirmations.push((rows.len(), subscriber.email, token));
+                }
+                outcome
+            }
+            Err(e) => RowOutcome::Invalid(e),
+        };
+        rows.push(ImportRow {
+            line,
+            email,
+            outcome,
+        });
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the import")
+        .map_err(e500)?;
+
+    for (index, email, token) in confirmations {
+        if let Err(e) = send_confirmation_email(&email_client, &email, &base_url.0, &token).await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to send a confirmation email");
+            rows[index].outcome = RowOutcome::Invalid(
+                "Imported, but the confirmation email could not be sent.".into(),
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(import_report(&rows)))
+}
+
+struct Upload {
+    file: Vec<u8>,
+    mode: ImportMode,
+    list: String,
+}
+
+async fn read_upload(mut payload: Multipart) -> Result<Upload, String> {
+    let mut file = None;
+    let mut mode = ImportMode::SendConfirmation;
+    let mut list = DEFAULT_LIST_SLUG.to_owned();
+    while let Some(mut field) = payload
+        .try_next()
+        .await
+        .map_err(|e| format!("The upload could not be read: {}", e))?
+    {
+        let mut content = Vec::new();
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|e| format!("The upload could not be read: {}", e))?
+        {
+            if content.len() + chunk.len() > MAX_UPLOAD_SIZE {
+                return Err("The uploaded file is too large.".into());
+            }
+            content.extend_from_slice(&chunk);
+        }
+        match field.name() {
+            "file" => file = Some(content),
+            "mode" => {
+                mode = match content.as_slice() {
+                    b"confirmed" => ImportMode::Confirmed,
+                    b"send_confirmation" => ImportMode::SendConfirmation,
+                    _ => return Err("Unknown import mode.".into()),
+                }
+            }
+            "list" => {
+                list = String::from_utf8(content)
+                    .map_err(|_| "The list is not valid UTF-8.".to_owned())?
+                    .trim()
+                    .to_owned()
+            }
+            _ => {}
+        }
+    }
+
+    match file {
+        Some(file) if !file.is_empty() => Ok(Upload { file, mode, list }),
+        _ => Err("Please choose a CSV file to import.".into()),
+    }
+}
+
+/// Extract `(line, email, name)` for every record of the CSV file.
+fn parse_csv(file: &[u8]) -> Result<Vec<(u64, String, String)>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(file);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("The CSV file could not be read: {}", e))?
+        .clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("The CSV file has no `{}` column.", name))
+    };
+    let email_column = column("email")?;
+    let name_column = column("name")?;
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("The CSV file could not be read: {}", e))?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        records.push((
+            line,
+            record.get(email_column).unwrap_or_default().to_owned(),
+            record.get(name_column).unwrap_or_default().to_owned(),
+        ));
+    }
+    Ok(records)
+}
+
+fn parse_row(email: String, name: String) -> Result<NewSubscriber, String> {
+    let email = SubscriberEmail::parse(email)?;
+    let name = SubscriberName::parse(name)?;
+    Ok(NewSubscriber { email, name })
+}
+
+/// Insert one subscriber and add them to the list, returning the token to
+/// send when a confirmation email is due. Existing list subscriptions are
+/// left untouched, whatever their status, and so are unsubscribed and
+/// suppressed addresses: an import must not override an unsubscription or
+/// resume emailing an address that bounced.
+async fn import_row(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber: &NewSubscriber,
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<(RowOutcome, Option<String>), anyhow::Error> {
+    let subscriber_id = match insert_subscriber(transaction, subscriber)
+        .await
+        .context("Failed to insert an imported subscriber")?
+    {
+        InsertOutcome::Inserted(subscriber_id) => subscriber_id,
+        InsertOutcome::Existing { status, .. }
+            if status == "unsubscribed" || status == "suppressed" =>
+        {
+            return Ok((
+                RowOutcome::Skipped(format!("Already subscribed ({}).", status)),
+                None,
+            ))
+        }
+        InsertOutcome::Existing { subscriber_id, .. } => subscriber_id,
+    };
+
+    let status = match mode {
+        ImportMode::Confirmed => "confirmed",
+        ImportMode::SendConfirmation => "pending_confirmation",
+    };
+    if let Some(existing) = add_to_list(transaction, list_id, subscriber_id, status)
+        .await
+        .context("Failed to add an imported subscriber to the list")?
+    {
+        return Ok((
+            RowOutcome::Skipped(format!("Already subscribed ({}).", existing)),
+            None,
+        ));
+    }
+
+    match mode {
+        ImportMode::Confirmed => {
+            sqlx::query!(
+                "UPDATE subscriptions SET status = 'confirmed', confirmed_at = now() WHERE id = $1",
+                subscriber_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to confirm an imported subscriber")?;
+            Ok((RowOutcome::Imported, None))
+        }
+        ImportMode::SendConfirmation => {
+            let token = generate_subcription_token();
+            store_token(transaction, subscriber_id, list_id, &token)
+                .await
+                .context("Failed to store the subscription token")?;
+            Ok((RowOutcome::Imported, Some(token)))
+        }
+    }
+}
+
+fn import_report(rows: &[ImportRow]) -> String {
+    let count = |f: fn(&RowOutcome) -> bool| rows.iter().filter(|r| f(&r.outcome)).count();
+    let imported = count(|o| matches!(o, RowOutcome::Imported));
+    let skipped = count(|o| matches!(o, RowOutcome::Skipped(_)));
+    let invalid = count(|o| matches!(o, RowOutcome::Invalid(_)));
+
+    let mut rows_html = String::new();
+    for row in rows {
+        let (result, detail) = match &row.outcome {
+            RowOutcome::Imported => ("imported", ""),
+            RowOutcome::Skipped(detail) => ("skipped", detail.as_str()),
+            RowOutcome::Invalid(detail) => ("error", detail.as_str()),
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.line,
+            htmlescape::encode_minimal(&row.email),
+            result,
+            htmlescape::encode_minimal(detail)
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import report</title>
+</head>
+<body>
+    <h1>Import report</h1>
+    <p>{imported} imported, {skipped} skipped, {invalid} with errors.</p>
+    <table>
+        <tr><th>Line</th><th>Email</th><th>Result</th><th>Details</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_csv;
+
+    #[test]
+    fn csv_columns_are_found_by_header_name() {
+        let file =
+            b"Name,Email,Notes\nUrsula,ursula@example.com,x\n\"Le Guin, U.\", le@example.com\n";
+
+        let records = parse_csv(file).unwrap();
+
+        assert_eq!(
+            records,
+            vec![
+                (2, "ursula@example.com".into(), "Ursula".into()),
+                (3, "le@example.com".into(), "Le Guin, U.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_without_an_email_column_is_rejected() {
+        let file = b"name\nUrsula\n";
+
+        assert!(parse_csv(file).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+mod export;
+mod get;
+mod import;
+mod post;
+
+pub use export::export_subscribers;
+pub use get::{list_subscribers, subscriber_details};
+pub use import::{import_subscribers, import_subscribers_form};
+pub use post::{
+    confirm_subscriber_manually, delete_subscriber, unsubscribe_subscriber, update_subscriber_tags,
+};
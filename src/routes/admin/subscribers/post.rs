@@ -0,0 +1,335 @@
+use crate::authentication::{Editors, RequireRole};
+use crate::mailing_lists::{confirm_pending_list_subscriptions, unsubscribe_from_all_lists};
+use crate::segment::{parse_attribute_name, parse_tag};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const SUPPRESSED: &str =
+    "The address is suppressed after a hard bounce or a spam complaint, its status cannot change.";
+
+pub async fn confirm_subscriber_manually(
+    _: RequireRole<Editors>,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    match set_subscriber_status(&pool, subscriber_id, "confirmed")
+        .await
+        .map_err(e500)?
+    {
+        Some(previous) if previous == "unsubscribed" => {
+            FlashMessage::error("The subscriber has unsubscribed, only they can subscribe again.")
+                .send()
+        }
+        Some(previous) if previous == "suppressed" => FlashMessage::error(SUPPRESSED).send(),
+        Some(_) => FlashMessage::info("The subscriber has been confirmed.").send(),
+        None => FlashMessage::error("The subscriber does not exist.").send(),
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+pub async fn unsubscribe_subscriber(
+    _: RequireRole<Editors>,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    match set_subscriber_status(&pool, subscriber_id, "unsubscribed")
+        .await
+        .map_err(e500)?
+    {
+        Some(previous) if previous == "suppressed" => FlashMessage::error(SUPPRESSED).send(),
+        Some(_) => FlashMessage::info("The subscriber has been unsubscribed.").send(),
+        None => FlashMessage::error("The subscriber does not exist.").send(),
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagsFormData {
+    /// Comma-separated tags.
+    tags: String,
+    /// One `name=value` pair per line.
+    attributes: String,
+}
+
+/// Replace the tags and custom attributes used to segment issues.
+pub async fn update_subscriber_tags(
+    _: RequireRole<Editors>,
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<TagsFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let location = format!("/admin/subscribers/{}", subscriber_id);
+    let parsed =
+        parse_tags(&form.tags).and_then(|tags| Ok((tags, parse_attributes(&form.attributes)?)));
+    let (tags, attributes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    if set_tags(&pool, subscriber_id, &tags, &attributes)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The tags and attributes have been saved.").send();
+    } else {
+        FlashMessage::error("The subscriber does not exist.").send();
+    }
+    Ok(see_other(&location))
+}
+
+fn parse_tags(tags: &str) -> Result<Vec<String>, String> {
+    let mut parsed = Vec::new();
+    for tag in tags.split(',').filter(|t| !t.trim().is_empty()) {
+        let tag = parse_tag(tag)?;
+        if !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_attributes(attributes: &str) -> Result<Vec<(String, String)>, String> {
+    let mut parsed: Vec<(String, String)> = Vec::new();
+    for line in attributes.lines().filter(|l| !l.trim().is_empty()) {
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("`{}` is not a `name=value` pair.", line.trim()))?;
+        let name = parse_attribute_name(name)?;
+        if parsed.iter().any(|(n, _)| *n == name) {
+            return Err(format!("The `{}` attribute is set twice.", name));
+        }
+        parsed.push((name, value.trim().to_owned()));
+    }
+    Ok(parsed)
+}
+
+/// Remove the subscriber together with their tokens and pending deliveries.
+/// The delivery history is kept, it is keyed by email and not by subscriber.
+pub async fn delete_subscriber(
+    _: RequireRole<Editors>,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    if remove_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The subscriber has been deleted.").send();
+    } else {
+        FlashMessage::error("The subscriber does not exist.").send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Returns the previous status, `None` if the subscriber does not exist.
+///
+/// Confirming also confirms the pending list subscriptions, unsubscribing
+/// removes the subscriber from every list. Subscribers who unsubscribed are
+/// not confirmed: they would stay off their lists anyway. Suppressed
+/// addresses are left alone.
+#[tracing::instrument(skip(pool))]
+async fn set_subscriber_status(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let previous = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber status")?;
+    let previous = match previous {
+        Some(r) => r.status,
+        None => return Ok(None),
+    };
+    if previous == "suppressed" || (status == "confirmed" && previous == "unsubscribed") {
+        return Ok(Some(previous));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2,
+            confirmed_at = CASE
+                WHEN $2 = 'confirmed' AND status <> 'confirmed' THEN now()
+                ELSE confirmed_at
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        status
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the subscriber status")?;
+    if status == "unsubscribed" {
+        unsubscribe_from_all_lists(&mut transaction, subscriber_id).await?;
+    } else {
+        confirm_pending_list_subscriptions(&mut transaction, subscriber_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(Some(previous))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn set_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tags: &[String],
+    attributes: &[(String, String)],
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let exists = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber")?
+    .is_some();
+    if !exists {
+        return Ok(false);
+    }
+
+    remove_tags(&mut transaction, subscriber_id).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+        "#,
+        subscriber_id,
+        tags
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber tags")?;
+    let (names, values): (Vec<_>, Vec<_>) = attributes.iter().cloned().unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (subscriber_id, name, value)
+        SELECT $1, name, value FROM UNNEST($2::text[], $3::text[]) AS a(name, value)
+        "#,
+        subscriber_id,
+        &names,
+        &values
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber attributes")?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+async fn remove_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber tags")?;
+    sqlx::query!(
+        "DELETE FROM subscriber_attributes WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete the subscriber attributes")?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn remove_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM subsciption_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens")?;
+    sqlx::query!(
+        "DELETE FROM list_subscriptions WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the list subscriptions")?;
+    remove_tags(&mut transaction, subscriber_id).await?;
+    sqlx::query!(
+        "DELETE FROM issue_events WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the tracking events")?;
+    let deleted = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to delete the subscriber")?;
+    let email = match deleted {
+        Some(r) => r.email,
+        None => return Ok(false),
+    };
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the pending deliveries")?;
+    sqlx::query!("DELETE FROM email_events WHERE email = $1", email)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the email events")?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_attributes, parse_tags};
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_comma_separated_and_deduplicated() {
+        assert_eq!(
+            parse_tags(" VIP, beta,, vip ").unwrap(),
+            vec!["vip".to_owned(), "beta".to_owned()]
+        );
+        assert_err!(parse_tags("not a tag"));
+    }
+
+    #[test]
+    fn attributes_are_one_pair_per_line() {
+        assert_eq!(
+            parse_attributes("country=FR\n\ncity = New York\n").unwrap(),
+            vec![
+                ("country".to_owned(), "FR".to_owned()),
+                ("city".to_owned(), "New York".to_owned())
+            ]
+        );
+        assert_err!(parse_attributes("country"));
+        assert_err!(parse_attributes("country=FR\ncountry=IT"));
+    }
+}
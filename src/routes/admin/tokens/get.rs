@@ -0,0 +1,96 @@
+use crate::authentication::UserID;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ApiToken {
+    token_id: Uuid,
+    name: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+pub async fn list_api_tokens(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut tokens_html = String::new();
+    for token in get_api_tokens(&pool, **user_id).await.map_err(e500)? {
+        let last_used_at = token
+            .last_used_at
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| "never".into());
+        let status_html = match token.revoked_at {
+            Some(revoked_at) => format!("revoked at {}", revoked_at.to_rfc3339()),
+            None => format!(
+                r#"<form action="/admin/tokens/{}/revoke" method="post"><button type="submit">Revoke</button></form>"#,
+                token.token_id
+            ),
+        };
+        writeln!(
+            tokens_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&token.name),
+            token.created_at.to_rfc3339(),
+            last_used_at,
+            status_html
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API tokens</title>
+</head>
+<body>
+    {msg_html}
+    <h1>API tokens</h1>
+    <table>
+        <tr><th>Name</th><th>Created at</th><th>Last used at</th><th></th></tr>
+        {tokens_html}
+    </table>
+    <h2>New token</h2>
+    <form action="/admin/tokens" method="post">
+        <label>Name
+            <input type="text" placeholder="What will this token be used for?" name="name">
+        </label>
+        <button type="submit">Create token</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get API tokens", skip(pool))]
+async fn get_api_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"SELECT token_id, name, created_at, last_used_at, revoked_at FROM api_tokens WHERE user_id = $1 ORDER BY created_at"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens of a user.")?;
+
+    Ok(tokens)
+}
@@ -0,0 +1,72 @@
+use crate::authentication::{create_api_token, revoke_api_token, Editors, RequireRole, UserID};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+/// Create a token and show it, once: only its hash is kept. Tokens are used
+/// to publish issues, so only editors and owners can have them.
+pub async fn create_token(
+    _: RequireRole<Editors>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error("The token needs a name.").send();
+        return Ok(see_other("/admin/tokens"));
+    }
+
+    let token = create_api_token(&pool, **user_id, &name)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API tokens</title>
+</head>
+<body>
+    <p>Your new token <b>{name}</b>:</p>
+    <pre><code id="api-token">{token}</code></pre>
+    <p>Copy it now, it will not be shown again.
+    Send it as <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+    <p><a href="/admin/tokens">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = htmlescape::encode_minimal(&name),
+            token = token.expose_secret()
+        )))
+}
+
+pub async fn revoke_token(
+    _: RequireRole<Editors>,
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_api_token(&pool, **user_id, token_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The token has been revoked.").send();
+    } else {
+        FlashMessage::error("The token does not exist or has already been revoked.").send();
+    }
+
+    Ok(see_other("/admin/tokens"))
+}
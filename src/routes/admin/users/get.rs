@@ -0,0 +1,145 @@
+use crate::authentication::{Owners, RequireRole, UserID};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    role: String,
+    disabled_at: Option<DateTime<Utc>>,
+}
+
+struct PendingInvite {
+    email: String,
+    role: String,
+    invited_at: DateTime<Utc>,
+}
+
+pub async fn list_users(
+    _: RequireRole<Owners>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut users_html = String::new();
+    for user in get_users(&pool).await.map_err(e500)? {
+        let status = match user.disabled_at {
+            Some(disabled_at) => format!("disabled at {}", disabled_at.to_rfc3339()),
+            None => "active".into(),
+        };
+        // Owners cannot lock themselves out.
+        let actions_html = if user.user_id == **user_id {
+            String::new()
+        } else {
+            let (action, label) = match user.disabled_at {
+                Some(_) => ("enable", "Enable"),
+                None => ("disable", "Disable"),
+            };
+            format!(
+                r#"<form action="/admin/users/{user_id}/{action}" method="post"><button type="submit">{label}</button></form>
+<form action="/admin/users/{user_id}/delete" method="post"><button type="submit">Delete</button></form>"#,
+                user_id = user.user_id
+            )
+        };
+        writeln!(
+            users_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&user.username),
+            user.role,
+            status,
+            actions_html
+        )
+        .unwrap();
+    }
+
+    let mut invites_html = String::new();
+    for invite in get_pending_invites(&pool).await.map_err(e500)? {
+        writeln!(
+            invites_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&invite.email),
+            invite.role,
+            invite.invited_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Users</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Users</h1>
+    <table>
+        <tr><th>Username</th><th>Role</th><th>Status</th><th></th></tr>
+        {users_html}
+    </table>
+    <h2>Pending invites</h2>
+    <table>
+        <tr><th>Email</th><th>Role</th><th>Invited at</th></tr>
+        {invites_html}
+    </table>
+    <h2>Invite a user</h2>
+    <form action="/admin/users/invite" method="post">
+        <label>Email <input type="email" name="email"></label>
+        <label>Role <select name="role">
+            <option value="viewer">Viewer</option>
+            <option value="editor">Editor</option>
+            <option value="owner">Owner</option>
+        </select></label>
+        <button type="submit">Send invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"SELECT user_id, username, role, disabled_at FROM users ORDER BY username"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the users.")?;
+
+    Ok(users)
+}
+
+#[tracing::instrument(name = "Get pending invites", skip(pool))]
+async fn get_pending_invites(pool: &PgPool) -> Result<Vec<PendingInvite>, anyhow::Error> {
+    let invites = sqlx::query_as!(
+        PendingInvite,
+        r#"
+        SELECT email, role, invited_at FROM user_invites
+        WHERE accepted_at IS NULL AND invited_at > now() - interval '7 days'
+        ORDER BY invited_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending invites.")?;
+
+    Ok(invites)
+}
@@ -0,0 +1,181 @@
+use crate::authentication::{Owners, RequireRole, Role, UserID};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::{invite_link, send_invite_email};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+/// Email a single-use link letting the invitee create their account.
+#[tracing::instrument(
+    name = "Invite a user",
+    skip_all,
+    fields(email = %form.email, role = %form.role)
+)]
+pub async fn invite_user(
+    _: RequireRole<Owners>,
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let parsed = SubscriberEmail::parse(form.0.email.trim().to_owned())
+        .and_then(|email| Ok((email, Role::parse(&form.role)?)));
+    let (email, role) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    // The invite is stored before the email goes out, so that a link is never
+    // sent for an invite that does not exist.
+    let invite_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invites (invite_id, email, role, invited_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        invite_id,
+        email.as_ref(),
+        role.as_str()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the invite")
+    .map_err(e500)?;
+    let link = invite_link(&base_url.0, invite_id, &secret);
+    send_invite_email(&email_client, &email, role, &link)
+        .await
+        .context("Failed to send the invite email")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("An invite has been sent to {}.", email.as_ref())).send();
+    Ok(see_other("/admin/users"))
+}
+
+pub async fn disable_user(
+    _: RequireRole<Owners>,
+    target_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_id = target_id.into_inner();
+    if target_id == **user_id {
+        FlashMessage::error("You cannot disable your own account.").send();
+    } else if set_user_disabled(&pool, target_id, true)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The user has been disabled.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+pub async fn enable_user(
+    _: RequireRole<Owners>,
+    target_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if set_user_disabled(&pool, target_id.into_inner(), false)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The user has been enabled.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+pub async fn delete_user(
+    _: RequireRole<Owners>,
+    target_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserID>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_id = target_id.into_inner();
+    if target_id == **user_id {
+        FlashMessage::error("You cannot delete your own account.").send();
+    } else if remove_user(&pool, target_id).await.map_err(e500)? {
+        FlashMessage::info("The user has been deleted.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+/// Disabled users can no longer log in, their sessions are ended and their
+/// API tokens are rejected.
+#[tracing::instrument(skip(pool))]
+async fn set_user_disabled(
+    pool: &PgPool,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users
+        SET disabled_at = CASE WHEN $2 THEN COALESCE(disabled_at, now()) END
+        WHERE user_id = $1
+        "#,
+        user_id,
+        disabled
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the user")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// The saved idempotent responses, the API tokens, the password reset tokens
+/// and the recovery codes of the user reference it, so they are deleted
+/// together with it. Deleting the idempotency rows waits for any request of
+/// the user that is still being processed.
+#[tracing::instrument(skip(pool))]
+async fn remove_user(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!("DELETE FROM idempotency WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the idempotency keys of the user")?;
+    sqlx::query!("DELETE FROM api_tokens WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the API tokens of the user")?;
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the password reset tokens of the user")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the recovery codes of the user")?;
+    let result = sqlx::query!("DELETE FROM users WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the user")?;
+    transaction.commit().await?;
+    Ok(result.rows_affected() == 1)
+}
@@ -0,0 +1,181 @@
+use crate::authentication::{bearer_token, validate_api_token, AuthError, Role};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::mailing_lists::{get_list_ids, set_issue_lists, ListError, DEFAULT_LIST_SLUG};
+use crate::markdown::IssueContent;
+use crate::routes::{
+    enqueue_delievery_tasks, error_chain_fmt, insert_newsletter_issue, parse_publish_at,
+};
+use crate::segment::Segment;
+use crate::templating::IssueTemplate;
+use actix_web::http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// RFC 3339 timestamp at which the issue should go out. Omit to send it now.
+    publish_at: Option<String>,
+    /// Slugs of the targeted lists. Omit to target the default list.
+    lists: Option<Vec<String>>,
+    /// Only send the issue to the subscribers matching this segment.
+    segment: Option<String>,
+    /// Leave the issue out of the public archive.
+    #[serde(default)]
+    private: bool,
+    /// Track the opens and clicks of the issue.
+    #[serde(default)]
+    tracking: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    #[serde(default)]
+    html: String,
+    #[serde(default)]
+    text: String,
+    /// Generates the HTML and plain text contents when set.
+    markdown: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct PublishResponse {
+    newsletter_issue_id: uuid::Uuid,
+}
+
+/// Publish an issue on behalf of the owner of the bearer token, who must be
+/// an editor or an owner.
+///
+/// Requests must carry an `Idempotency-Key` header: retrying a request with the
+/// same key returns the original response instead of publishing the issue twice.
+#[tracing::instrument(
+    name = "Publish a newletter issue through the API",
+    skip(body, pool, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let token = bearer_token(request.headers()).map_err(PublishError::AuthError)?;
+    let (user_id, role) = validate_api_token(token, &pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    match role {
+        Role::Editor | Role::Owner => {}
+        Role::Viewer => {
+            return Err(PublishError::Forbidden(anyhow::anyhow!(
+                "Only editors and owners can publish issues."
+            )))
+        }
+    }
+
+    let idempotency_key =
+        idempotency_key(request.headers()).map_err(PublishError::ValidationError)?;
+    let BodyData {
+        title,
+        content,
+        publish_at,
+        lists,
+        segment,
+        private,
+        tracking,
+    } = body.0;
+    let list_slugs = lists.unwrap_or_else(|| vec![DEFAULT_LIST_SLUG.to_owned()]);
+    let publish_at =
+        parse_publish_at(publish_at.as_deref()).map_err(PublishError::ValidationError)?;
+    let segment = Segment::parse(segment.as_deref().unwrap_or_default())
+        .map_err(|e| PublishError::ValidationError(anyhow::anyhow!(e)))?;
+    let content = IssueContent::new(content.markdown, content.html, content.text);
+    IssueTemplate::parse(&content.html, &content.text)
+        .map_err(|e| PublishError::ValidationError(anyhow::anyhow!(e)))?;
+
+    let mut tx = match try_processing(&pool, &idempotency_key, user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_resp) => return Ok(saved_resp),
+    };
+
+    let list_ids = get_list_ids(&mut tx, &list_slugs)
+        .await
+        .map_err(|e| match e {
+            ListError::UnexpectedError(e) => PublishError::UnexpectedError(e.into()),
+            e => PublishError::ValidationError(e.into()),
+        })?;
+
+    let issue_id = insert_newsletter_issue(
+        &mut tx, &title, &content, publish_at, &segment, private, tracking,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+
+    set_issue_lists(&mut tx, issue_id, &list_ids)
+        .await
+        .context("Failed to target the newsletter issue")?;
+
+    enqueue_delievery_tasks(&mut tx, issue_id, &segment)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    let resp = HttpResponse::Accepted().json(PublishResponse {
+        newsletter_issue_id: issue_id,
+    });
+    let resp = save_response(tx, &idempotency_key, user_id, resp).await?;
+
+    Ok(resp)
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<IdempotencyKey, anyhow::Error> {
+    let key = headers
+        .get("Idempotency-Key")
+        .context("missing Idempotency-Key header")?
+        .to_str()
+        .context("Idempotency-Key header was not properly utf-8 encoded")?;
+
+    key.to_owned().try_into()
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    Forbidden(anyhow::Error),
+    #[error("{0}")]
+    ValidationError(anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PublishError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            PublishError::ValidationError(e) => HttpResponse::BadRequest().body(e.to_string()),
+            PublishError::Forbidden(e) => HttpResponse::Forbidden().body(e.to_string()),
+            PublishError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Bearer realm="publish""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
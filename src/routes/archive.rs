@@ -0,0 +1,281 @@
+use crate::startup::ApplicationBaseUrl;
+use crate::templating::{IssueTemplate, MergeFields};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Number of issues in the Atom feed.
+const FEED_SIZE: i64 = 20;
+
+/// The URL-friendly name of an issue in the archive. The id prefix keeps
+/// issues with the same title apart.
+pub fn issue_slug(title: &str, issue_id: Uuid) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = if slug.is_empty() { "issue" } else { slug };
+    format!("{}-{}", slug, &issue_id.to_string()[..8])
+}
+
+pub fn archive_link(base_url: &str, slug: &str) -> String {
+    format!("{}/archive/{}", base_url, slug)
+}
+
+/// Add a "view in browser" link at the top of both bodies of an email.
+pub fn with_web_view_link(html: &str, text: &str, url: &str) -> (String, String) {
+    let link = format!(
+        r#"<p><a href="{}">View this email in your browser</a></p>"#,
+        htmlescape::encode_minimal(url)
+    );
+    // Keep the link inside the body of full HTML documents.
+    let insert_at = html
+        .find("<body")
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let html = format!("{}{}\n{}", &html[..insert_at], link, &html[insert_at..]);
+    let text = format!("View this email in your browser: {}\n\n{}", url, text);
+    (html, text)
+}
+
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    slug: String,
+    html_content: String,
+    text_content: String,
+    publish_at: DateTime<Utc>,
+}
+
+impl ArchivedIssue {
+    /// The HTML content with merge fields filled in for an anonymous reader.
+    /// Editors can write raw HTML, so it is sanitized before being served on
+    /// our own origin. Anonymous readers have no unsubscribe link: links to
+    /// it are left out.
+    fn web_html(&self) -> String {
+        let html = match IssueTemplate::parse(&self.html_content, &self.text_content) {
+            Ok(template) => {
+                template
+                    .render(&MergeFields {
+                        name: "",
+                        email: "",
+                        unsubscribe_url: "",
+                        confirmed_at: None,
+                    })
+                    .0
+            }
+            Err(_) => self.html_content.clone(),
+        };
+        ammonia::Builder::default()
+            .attribute_filter(|_, attribute, value| match (attribute, value) {
+                ("href", "") => None,
+                _ => Some(value.into()),
+            })
+            .clean(&html)
+            .to_string()
+    }
+}
+
+pub async fn archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut issues_html = String::new();
+    for issue in get_archived_issues(&pool, None).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li>{} - <a href="/archive/{}">{}</a></li>"#,
+            issue.publish_at.format("%Y-%m-%d"),
+            htmlescape::encode_minimal(&issue.slug),
+            htmlescape::encode_minimal(&issue.title)
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <link rel="alternate" type="application/atom+xml" href="/archive/feed.xml">
+    <title>Archive</title>
+</head>
+<body>
+    <h1>Archive</h1>
+    <ul>
+        {issues_html}
+    </ul>
+    <p><a href="/archive/feed.xml">Atom feed</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>{date}</p>
+    {content}
+    <p><a href="/archive">&lt;- Archive</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            date = issue.publish_at.format("%Y-%m-%d"),
+            content = issue.web_html(),
+        )))
+}
+
+pub async fn archive_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let issues = get_archived_issues(&pool, Some(FEED_SIZE))
+        .await
+        .map_err(e500)?;
+    let updated = issues
+        .first()
+        .map(|i| i.publish_at)
+        .unwrap_or_else(Utc::now);
+
+    let mut entries = String::new();
+    for issue in &issues {
+        write!(
+            entries,
+            r#"
+  <entry>
+    <title>{}</title>
+    <id>urn:uuid:{}</id>
+    <link href="{}"/>
+    <updated>{}</updated>
+    <content type="html">{}</content>
+  </entry>"#,
+            htmlescape::encode_minimal(&issue.title),
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&archive_link(base_url, &issue.slug)),
+            issue.publish_at.to_rfc3339(),
+            htmlescape::encode_minimal(&issue.web_html())
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Newsletter archive</title>
+  <id>{base_url}/archive</id>
+  <link rel="self" href="{base_url}/archive/feed.xml"/>
+  <link href="{base_url}/archive"/>
+  <updated>{updated}</updated>
+  <author><name>Newsletter</name></author>{entries}
+</feed>
+"#,
+            base_url = htmlescape::encode_minimal(base_url),
+            updated = updated.to_rfc3339(),
+        )))
+}
+
+/// Public issues that have gone out, most recent first.
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issues(
+    pool: &PgPool,
+    limit: Option<i64>,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, slug AS "slug!", html_content, text_content, publish_at
+        FROM newsletter_issues
+        WHERE status = 'published' AND cancelled_at IS NULL AND NOT private
+          AND publish_at <= now() AND slug IS NOT NULL
+        ORDER BY publish_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the archived issues")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, slug AS "slug!", html_content, text_content, publish_at
+        FROM newsletter_issues
+        WHERE status = 'published' AND cancelled_at IS NULL AND NOT private
+          AND publish_at <= now() AND slug = $1
+        "#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve an archived issue")?;
+    Ok(issue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_slug, with_web_view_link};
+    use uuid::Uuid;
+
+    #[test]
+    fn slugs_are_built_from_the_title_and_the_id() {
+        let id = Uuid::parse_str("0a1b2c3d-0000-0000-0000-000000000000").unwrap();
+
+        assert_eq!(issue_slug("Hello, World!", id), "hello-world-0a1b2c3d");
+        assert_eq!(issue_slug("  ¡¿?! ", id), "issue-0a1b2c3d");
+    }
+
+    #[test]
+    fn the_web_view_link_goes_at_the_top_of_the_body() {
+        let (html, text) = with_web_view_link(
+            "<html><body class=\"x\"><p>Hi</p></body></html>",
+            "Hi",
+            "https://example.com/archive/hi",
+        );
+
+        assert_eq!(
+            html,
+            "<html><body class=\"x\"><p><a href=\"https://example.com/archive/hi\">View this email in your browser</a></p>\n<p>Hi</p></body></html>"
+        );
+        assert_eq!(
+            text,
+            "View this email in your browser: https://example.com/archive/hi\n\nHi"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::authentication::{create_user, Role};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use crate::utils::see_other;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct InviteParameters {
+    invite_id: Uuid,
+    tag: String,
+}
+
+impl InviteParameters {
+    fn verify(&self, secret: &HmacSecret) -> Result<Uuid, anyhow::Error> {
+        let tag = hex::decode(&self.tag)?;
+
+        let mac = invite_mac(self.invite_id, secret);
+        mac.verify_slice(&tag)?;
+
+        Ok(self.invite_id)
+    }
+
+    fn query_string(&self) -> String {
+        format!("invite_id={}&tag={}", self.invite_id, self.tag)
+    }
+}
+
+fn invite_mac(invite_id: Uuid, secret: &HmacSecret) -> Hmac<sha2::Sha256> {
+    let query_string = format!("invite_id={}", invite_id);
+
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(query_string.as_bytes());
+    mac
+}
+
+/// Build the signed link letting an invited user create their account.
+pub fn invite_link(base_url: &str, invite_id: Uuid, secret: &HmacSecret) -> String {
+    let tag = hex::encode(invite_mac(invite_id, secret).finalize().into_bytes());
+
+    format!("{}/invite?invite_id={}&tag={}", base_url, invite_id, tag)
+}
+
+pub async fn send_invite_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    role: Role,
+    invite_link: &str,
+) -> Result<(), anyhow::Error> {
+    let html_content = format!(
+        "You have been invited to help run our newsletter with the {} role.<br /> \
+    Click <a href=\"{}\">here</a> to create your account. The link expires in 7 days.",
+        role, invite_link
+    );
+    let text_content = format!(
+        "You have been invited to help run our newsletter with the {} role.\n \
+    Visit {} to create your account. The link expires in 7 days.",
+        role, invite_link
+    );
+    email_client
+        .send_email(email, "Your invite", &html_content, &text_content)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum InviteError {
+    #[error("The invite link is invalid, has expired or has already been used.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for InviteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for InviteError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InviteError::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            InviteError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+pub async fn invite_form(
+    parameters: web::Query<InviteParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, InviteError> {
+    let invite_id = parameters
+        .verify(&secret)
+        .map_err(InviteError::InvalidLink)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let (email, role) = get_pending_invite(&mut transaction, invite_id)
+        .await?
+        .ok_or_else(|| InviteError::InvalidLink(anyhow::anyhow!("The invite is not pending.")))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Create your account</title>
+</head>
+<body>
+    {msg_html}
+    <p>You have been invited with the {role} role. Choose your username and password.</p>
+    <form action="/invite?{query_string}" method="post">
+        <label>Username
+            <input type="text" name="username" value="{email}">
+        </label>
+        <br>
+        <label>Password
+            <input type="password" placeholder="Enter password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" placeholder="Type the password again" name="password_check">
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+            email = htmlescape::encode_attribute(&email),
+            query_string = htmlescape::encode_minimal(&parameters.query_string()),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInviteFormData {
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+/// Create the account of an invited user. Each invite can only be used once.
+#[tracing::instrument(name = "Accept an invite", skip_all, fields(invite_id=%parameters.invite_id))]
+pub async fn accept_invite(
+    parameters: web::Query<InviteParameters>,
+    form: web::Form<AcceptInviteFormData>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, InviteError> {
+    let invite_id = parameters
+        .verify(&secret)
+        .map_err(InviteError::InvalidLink)?;
+    let form_location = format!("/invite?{}", parameters.query_string());
+
+    let AcceptInviteFormData {
+        username,
+        password,
+        password_check,
+    } = form.0;
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&form_location));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&form_location));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let (email, role) = get_pending_invite(&mut transaction, invite_id)
+        .await?
+        .ok_or_else(|| InviteError::InvalidLink(anyhow::anyhow!("The invite is not pending.")))?;
+
+    let created = create_user(
+        &mut transaction,
+        username,
+        Some(&email),
+        password,
+        Role::parse(&role).map_err(anyhow::Error::msg)?,
+    )
+    .await?;
+    if created.is_none() {
+        FlashMessage::error("This username is already taken.").send();
+        return Ok(see_other(&form_location));
+    }
+
+    sqlx::query!(
+        "UPDATE user_invites SET accepted_at = now() WHERE invite_id = $1",
+        invite_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the invite as accepted")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user")?;
+
+    FlashMessage::info("Your account has been created, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// The email and role of an invite that has not been used yet. Invites expire
+/// after 7 days.
+#[tracing::instrument(skip(transaction))]
+async fn get_pending_invite(
+    transaction: &mut Transaction<'_, Postgres>,
+    invite_id: Uuid,
+) -> Result<Option<(String, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role FROM user_invites
+        WHERE invite_id = $1 AND accepted_at IS NULL AND invited_at > now() - interval '7 days'
+        FOR UPDATE
+        "#,
+        invite_id
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to retrieve the invite")?;
+
+    Ok(row.map(|r| (r.email, r.role)))
+}
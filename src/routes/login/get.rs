@@ -1,4 +1,6 @@
+use crate::session_state::TypedSession;
 use crate::startup::HmacSecret;
+use crate::utils::{e500, see_other};
 use actix_web::cookie::time::Duration;
 use actix_web::cookie::Cookie;
 use actix_web::http::header::ContentType;
@@ -64,8 +66,44 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 </label>
 <button type="submit">Login</button>
 </form>
+<p><a href="/password_reset">Forgot your password?</a></p>
 </body>
 </html>"#,
         ));
     resp
 }
+
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_two_factor_pending().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/login/two_factor" method="post">
+        <label>Code from your authenticator app, or one of your recovery codes
+            <input type="text" autocomplete="one-time-code" name="code">
+        </label>
+        <button type="submit">Log in</button>
+    </form>
+    <p><a href="/login">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
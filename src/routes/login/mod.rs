@@ -1,5 +1,5 @@
 mod get;
 mod post;
 
-pub use get::login_form;
-pub use post::login;
+pub use get::{login_form, two_factor_form};
+pub use post::{login, two_factor};
@@ -1,14 +1,19 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    get_session_version, has_totp, must_change_password, validate_credentials,
+    verify_second_factor, AuthError, Credentials, SecondFactor, TotpKey,
+};
 use crate::routes::error_chain_fmt;
-use crate::session_state::TypedSession;
+use crate::session_state::{TwoFactorPending, TypedSession};
+use crate::utils::{e500, see_other};
 use actix_session::Session;
 use actix_web::error::InternalError;
 use actix_web::web;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::FlashMessage;
 use reqwest::header::LOCATION;
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(thiserror::Error)]
 pub enum LoginError {
@@ -48,12 +53,23 @@ pub async fn login(
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            let has_totp = has_totp(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            let location = if has_totp {
+                session.renew();
+                session.remove_user_id();
+                session
+                    .insert_two_factor_pending(&TwoFactorPending { user_id })
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                "/login/two_factor"
+            } else {
+                start_session(&session, &pool, user_id)
+                    .await
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+            };
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, location))
                 .finish())
         }
         Err(e) => {
@@ -66,6 +82,67 @@ pub async fn login(
     }
 }
 
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: Secret<String>,
+}
+
+/// The second login step, for users with TOTP enabled. Once locked out after
+/// too many wrong codes, the password has to be entered again.
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+pub async fn two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    totp_key: web::Data<TotpKey>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pending = match session.get_two_factor_pending().map_err(e500)? {
+        Some(pending) => pending,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&pending.user_id));
+
+    match verify_second_factor(&pool, &totp_key, pending.user_id, form.code.expose_secret())
+        .await
+        .map_err(e500)?
+    {
+        SecondFactor::Accepted => {
+            let location = start_session(&session, &pool, pending.user_id)
+                .await
+                .map_err(e500)?;
+            Ok(see_other(location))
+        }
+        SecondFactor::Rejected => {
+            FlashMessage::error("The code is incorrect.").send();
+            Ok(see_other("/login/two_factor"))
+        }
+        SecondFactor::LockedOut => {
+            session.log_out();
+            FlashMessage::error("Too many wrong codes, please try again later.").send();
+            Ok(see_other("/login"))
+        }
+    }
+}
+
+/// Log the user in, in a new session. Returns where to send them: users who
+/// must change their password go do it first.
+async fn start_session(
+    session: &TypedSession,
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<&'static str, anyhow::Error> {
+    let session_version = get_session_version(pool, user_id).await?;
+    session.renew();
+    session.remove_two_factor_pending();
+    session.insert_user_id(user_id)?;
+    session.insert_session_version(session_version)?;
+    if must_change_password(pool, user_id).await? {
+        FlashMessage::error("You must change your password before going further.").send();
+        return Ok("/admin/password");
+    }
+    Ok("/admin/dashboard")
+}
+
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
 
@@ -1,15 +1,29 @@
 mod admin;
+mod api;
+mod archive;
 mod health_check;
 mod home;
+mod invite;
 mod login;
-mod newsletter;
+mod password_reset;
+mod setup;
 mod subscription_confirm;
 mod subscriptions;
+mod tracking;
+mod unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use api::*;
+pub use archive::*;
 pub use health_check::*;
 pub use home::*;
+pub use invite::*;
 pub use login::*;
-pub use newsletter::*;
+pub use password_reset::*;
+pub use setup::*;
 pub use subscription_confirm::*;
 pub use subscriptions::*;
+pub use tracking::*;
+pub use unsubscribe::*;
+pub use webhooks::*;
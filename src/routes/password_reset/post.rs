@@ -0,0 +1,249 @@
+use super::get::ResetParameters;
+use crate::authentication::{change_password, end_all_sessions, hash_token};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::error_chain_fmt;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Every request for a reset link takes at least this long, whether or not
+/// the account exists, so that timing does not tell usernames apart.
+const FORGOT_PASSWORD_RESPONSE_TIME: Duration = Duration::from_millis(500);
+
+#[derive(thiserror::Error)]
+pub enum PasswordResetError {
+    #[error("The reset link is invalid, has expired or has already been used.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PasswordResetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PasswordResetError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PasswordResetError::InvalidToken => StatusCode::UNAUTHORIZED,
+            PasswordResetError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    username: String,
+}
+
+/// Email a reset link to the user, if they exist, are enabled and have an
+/// email address. The response is the same either way.
+#[tracing::instrument(name = "Request a password reset", skip_all)]
+pub async fn forgot_password(
+    form: web::Form<ForgotPasswordFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let started_at = tokio::time::Instant::now();
+
+    if let Some((email, token)) = issue_reset_token(&pool, form.username.trim())
+        .await
+        .map_err(e500)?
+    {
+        let link = format!(
+            "{}/password_reset/confirm?token={}",
+            base_url.0,
+            token.expose_secret()
+        );
+        // Sending takes a while: waiting for it would give away that the
+        // account exists.
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send_reset_email(&email_client, &email, &link).await {
+                tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to send a password reset email.");
+            }
+        });
+    }
+
+    tokio::time::sleep_until(started_at + FORGOT_PASSWORD_RESPONSE_TIME).await;
+    FlashMessage::info(
+        "If the account exists and has an email address, a reset link has been sent to it.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+/// Set a new password with a reset link. The link cannot be used again and
+/// every session of the user is ended.
+#[tracing::instrument(name = "Reset a password", skip_all)]
+pub async fn reset_password(
+    parameters: web::Query<ResetParameters>,
+    form: web::Form<ResetPasswordFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PasswordResetError> {
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&format!(
+            "/password_reset/confirm?token={}",
+            urlencoding::encode(&parameters.token)
+        )));
+    }
+
+    // The token is only spent once the new password is stored.
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let user_id = consume_reset_token(&mut transaction, &parameters.token)
+        .await?
+        .ok_or(PasswordResetError::InvalidToken)?;
+    change_password(user_id, form.0.new_password, &mut transaction).await?;
+    end_all_sessions(&mut transaction, user_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset")?;
+
+    FlashMessage::info("Your password has been reset, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// Only the hash of the token is stored.
+#[tracing::instrument(skip(pool))]
+async fn issue_reset_token(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(SubscriberEmail, Secret<String>)>, anyhow::Error> {
+    let user = sqlx::query!(
+        r#"
+        SELECT user_id, email AS "email!" FROM users
+        WHERE username = $1 AND disabled_at IS NULL AND email IS NOT NULL
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user")?;
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let random_bytes: [u8; 32] = rand::thread_rng().gen();
+    let token = hex::encode(random_bytes);
+    sqlx::query!(
+        r#"INSERT INTO password_reset_tokens (token_hash, user_id, created_at) VALUES ($1, $2, now())"#,
+        hash_token(&token),
+        user.user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a password reset token")?;
+
+    let email = SubscriberEmail::parse(user.email).map_err(anyhow::Error::msg)?;
+    Ok(Some((email, Secret::new(token))))
+}
+
+async fn send_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    reset_link: &str,
+) -> Result<(), anyhow::Error> {
+    let html_content = format!(
+        "Someone asked to reset your password.<br /> \
+    Click <a href=\"{}\">here</a> to choose a new one. The link expires in 1 hour. \
+    If it was not you, you can ignore this email.",
+        reset_link
+    );
+    let text_content = format!(
+        "Someone asked to reset your password.\n \
+    Visit {} to choose a new one. The link expires in 1 hour. \
+    If it was not you, you can ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(email, "Reset your password", &html_content, &text_content)
+        .await?;
+
+    Ok(())
+}
+
+/// Reset tokens are valid for an hour, for enabled users.
+pub(super) async fn is_valid_reset_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT t.user_id FROM password_reset_tokens t
+        JOIN users u USING (user_id)
+        WHERE t.token_hash = $1
+            AND t.created_at > now() - interval '1 hour'
+            AND u.disabled_at IS NULL
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a password reset token")?;
+
+    Ok(row.is_some())
+}
+
+/// Returns the user the token was issued to if it is valid. All the tokens
+/// of that user are deleted, so that older links cannot be used either; the
+/// deletion only sticks if the caller commits the transaction.
+#[tracing::instrument(skip_all)]
+async fn consume_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT t.user_id FROM password_reset_tokens t
+        JOIN users u USING (user_id)
+        WHERE t.token_hash = $1
+            AND t.created_at > now() - interval '1 hour'
+            AND u.disabled_at IS NULL
+        FOR UPDATE OF t
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a password reset token")?;
+    let user_id = match row {
+        Some(row) => row.user_id,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete the password reset tokens")?;
+
+    Ok(Some(user_id))
+}
@@ -0,0 +1,192 @@
+use crate::authentication::{create_user, hash_token, Role};
+use crate::bootstrap::{has_users, lock_users};
+use crate::domain::SubscriberEmail;
+use crate::routes::error_chain_fmt;
+use crate::utils::see_other;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgExecutor, PgPool};
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct SetupParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum SetupError {
+    #[error("The setup token is invalid.")]
+    InvalidToken,
+    #[error("The setup is already complete.")]
+    AlreadyDone,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SetupError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SetupError::InvalidToken => StatusCode::UNAUTHORIZED,
+            SetupError::AlreadyDone => StatusCode::NOT_FOUND,
+            SetupError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The first-run setup page, available while there is no user. The token is
+/// logged on startup.
+pub async fn setup_form(
+    parameters: web::Query<SetupParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, SetupError> {
+    if has_users(pool.get_ref()).await? {
+        return Err(SetupError::AlreadyDone);
+    }
+    check_setup_token(pool.get_ref(), &parameters.token).await?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Setup</title>
+</head>
+<body>
+    {msg_html}
+    <p>Create the first owner of the newsletter.</p>
+    <form action="/setup?token={token}" method="post">
+        <label>Username
+            <input type="text" name="username">
+        </label>
+        <br>
+        <label>Email, to be able to reset your password
+            <input type="email" name="email">
+        </label>
+        <br>
+        <label>Password
+            <input type="password" placeholder="Enter password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" placeholder="Type the password again" name="password_check">
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+            token = htmlescape::encode_attribute(&parameters.token),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SetupFormData {
+    username: String,
+    /// Needed to reset the password.
+    #[serde(default)]
+    email: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+/// Create the first owner. The setup token cannot be used again.
+#[tracing::instrument(name = "Create the first owner", skip_all)]
+pub async fn setup(
+    parameters: web::Query<SetupParameters>,
+    form: web::Form<SetupFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SetupError> {
+    let form_location = format!("/setup?token={}", urlencoding::encode(&parameters.token));
+    let SetupFormData {
+        username,
+        email,
+        password,
+        password_check,
+    } = form.0;
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&form_location));
+    }
+    let email = match email.trim() {
+        "" => None,
+        email => match SubscriberEmail::parse(email.to_owned()) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&form_location));
+            }
+        },
+    };
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&form_location));
+    }
+
+    if has_users(pool.get_ref()).await? {
+        return Err(SetupError::AlreadyDone);
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if lock_users(&mut transaction).await? {
+        return Err(SetupError::AlreadyDone);
+    }
+    check_setup_token(&mut transaction, &parameters.token).await?;
+    create_user(
+        &mut transaction,
+        username,
+        email.as_ref().map(|e| e.as_ref()),
+        password,
+        Role::Owner,
+    )
+    .await?
+    .context("There is no user, yet the username is taken")?;
+    sqlx::query!("DELETE FROM setup_tokens")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the setup token")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the first owner")?;
+
+    FlashMessage::info("Your account has been created, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+async fn check_setup_token<'e>(
+    executor: impl PgExecutor<'e>,
+    token: &str,
+) -> Result<(), SetupError> {
+    sqlx::query!(
+        "SELECT token_hash FROM setup_tokens WHERE token_hash = $1",
+        hash_token(token)
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve the setup token")?
+    .ok_or(SetupError::InvalidToken)?;
+
+    Ok(())
+}
@@ -1,5 +1,10 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use crate::mailing_lists::set_list_subscription_status;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -7,58 +12,146 @@ pub struct ConfirmParameters {
     subscription_token: String,
 }
 
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("The subscription token is unknown or has already been used.")]
+    UnknownToken,
+    #[error("The subscription token has expired.")]
+    ExpiredToken,
+    #[error("The address bounced or complained, it cannot be subscribed again.")]
+    SuppressedAddress,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::ExpiredToken => StatusCode::GONE,
+            ConfirmError::SuppressedAddress => StatusCode::FORBIDDEN,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
 pub async fn confirm(
     pool: web::Data<PgPool>,
     parameters: web::Query<ConfirmParameters>,
-) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection for transaction")?;
 
-    match id {
-        None => return HttpResponse::Unauthorized().finish(),
-        Some(subcriber_id) => {
-            if confirm_subscriber(&pool, subcriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-        }
+    let token = get_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscription token")?
+        .ok_or(ConfirmError::UnknownToken)?;
+
+    if token.used_at.is_some() {
+        return Err(ConfirmError::UnknownToken);
+    }
+    if token.expires_at <= Utc::now() {
+        return Err(ConfirmError::ExpiredToken);
     }
 
-    HttpResponse::Ok().finish()
+    mark_token_as_used(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to mark the subscription token as used")?;
+    if !confirm_subscriber(&mut transaction, token.subscriber_id)
+        .await
+        .context("Failed to mark the subscriber as confirmed")?
+    {
+        return Err(ConfirmError::SuppressedAddress);
+    }
+    set_list_subscription_status(
+        &mut transaction,
+        token.list_id,
+        token.subscriber_id,
+        "confirmed",
+    )
+    .await
+    .context("Failed to confirm the list subscription")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the confirmation")?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark subcriber as confirmed", skip(subcriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subcriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+struct SubscriptionToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+/// Suppressed addresses stay suppressed: returns `false` for them.
+#[tracing::instrument(name = "Mark subcriber as confirmed", skip(subcriber_id, transaction))]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subcriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed', confirmed_at = now()
+        WHERE id = $1 AND status <> 'suppressed'
+        "#,
         subcriber_id,
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Unable to execute update status query: {:?}", e);
         e
     })?;
-    Ok(())
+    Ok(result.rows_affected() == 1)
 }
 
-#[tracing::instrument(name = "Get subcriber_id from token", skip(token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+#[tracing::instrument(name = "Get subscription token", skip(token, transaction))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
     token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let record = sqlx::query!(
-        "SELECT subscriber_id FROM subsciption_tokens WHERE subscription_token = $1",
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let record = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+    SELECT subscriber_id, list_id, expires_at, used_at
+    FROM subsciption_tokens
+    WHERE subscription_token = $1
+    FOR UPDATE
+        "#,
         token
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Unable to execute query: {:?}", e);
         e
     })?;
 
-    Ok(record.map(|r| r.subscriber_id))
+    Ok(record)
+}
+
+#[tracing::instrument(name = "Mark subscription token as used", skip(token, transaction))]
+async fn mark_token_as_used(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subsciption_tokens SET used_at = now() WHERE subscription_token = $1",
+        token
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
@@ -1,6 +1,9 @@
 use crate::{
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
     email_client::EmailClient,
+    mailing_lists::{
+        add_to_list, get_list_id, set_list_subscription_status, ListError, DEFAULT_LIST_SLUG,
+    },
     startup::ApplicationBaseUrl,
 };
 use actix_web::{
@@ -9,16 +12,34 @@ use actix_web::{
     HttpResponse, ResponseError,
 };
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use sqlx::PgPool;
 use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
+/// How long a confirmation link stays valid after it has been issued.
+const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 24;
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Slug of the list to join, the default list when missing.
+    list: Option<String>,
+}
+
+impl FormData {
+    fn list_slug(&self) -> String {
+        list_slug_or_default(self.list.as_deref())
+    }
+}
+
+fn list_slug_or_default(slug: Option<&str>) -> String {
+    match slug.map(str::trim) {
+        None | Some("") => DEFAULT_LIST_SLUG.to_owned(),
+        Some(slug) => slug.to_owned(),
+    }
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -46,32 +67,194 @@ pub async fn subscribe(
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let list_slug = form.list_slug();
     let subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a postgres connection for transaction")?;
+    let list_id = get_list_id(&mut transaction, &list_slug).await?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &subscriber)
+    let subscriber_id = match insert_subscriber(&mut transaction, &subscriber)
         .await
-        .context("")?;
+        .context("Failed to insert the new subscriber")?
+    {
+        InsertOutcome::Inserted(subscriber_id) => subscriber_id,
+        // Addresses that bounced or complained are not emailed again, but
+        // get the same answer as everybody else.
+        InsertOutcome::Existing { status, .. } if status == "suppressed" => {
+            return Ok(HttpResponse::Ok().finish());
+        }
+        InsertOutcome::Existing {
+            subscriber_id,
+            status,
+        } => {
+            if status == "unsubscribed" {
+                restart_double_opt_in(&mut transaction, subscriber_id, &subscriber)
+                    .await
+                    .context("Failed to reset the unsubscribed subscriber")?;
+            }
+            subscriber_id
+        }
+    };
 
-    let subscription_token = generate_subcription_token();
+    let subscription_token = match add_to_list(
+        &mut transaction,
+        list_id,
+        subscriber_id,
+        "pending_confirmation",
+    )
+    .await
+    .context("Failed to add the subscriber to the list")?
+    {
+        None => {
+            let subscription_token = generate_subcription_token();
+            store_token(
+                &mut transaction,
+                subscriber_id,
+                list_id,
+                &subscription_token,
+            )
+            .await
+            .context("Failed to store the subscription token")?;
+            subscription_token
+        }
+        // Answer exactly like a fresh subscription so the form does not
+        // reveal who is already on the list.
+        Some(status) if status == "confirmed" => {
+            return Ok(HttpResponse::Ok().finish());
+        }
+        Some(status) => {
+            if status == "unsubscribed" {
+                set_list_subscription_status(
+                    &mut transaction,
+                    list_id,
+                    subscriber_id,
+                    "pending_confirmation",
+                )
+                .await
+                .context("Failed to restart the list subscription")?;
+            }
+            reissue_token(&mut transaction, subscriber_id, list_id)
+                .await
+                .context("Failed to reissue a subscription token")?
+        }
+    };
+
+    transaction.commit().await.context("")?;
+
+    send_confirmation_email(
+        &email_client,
+        &subscriber.email,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+    list: Option<String>,
+}
 
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+/// Issue a fresh confirmation link to a subscriber who has not confirmed yet.
+///
+/// Unknown or already confirmed addresses get the same `200 OK`, so the
+/// endpoint cannot be used to find out who is on the list.
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let list_slug = list_slug_or_default(form.list.as_deref());
+    let email = SubscriberEmail::parse(form.0.email).map_err(SubscribeError::ValidationError)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection for transaction")?;
+    let list_id = get_list_id(&mut transaction, &list_slug).await?;
+
+    let subscriber_id = match get_pending_subscriber_id(&mut transaction, &email, list_id)
         .await
-        .context("")?;
+        .context("Failed to look up the pending subscriber")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    let subscription_token = reissue_token(&mut transaction, subscriber_id, list_id)
+        .await
+        .context("Failed to reissue a subscription token")?;
 
     transaction.commit().await.context("")?;
 
-    send_confirmation_email(&email_client, subscriber, &base_url.0, "mytoken")
+    send_confirmation_email(&email_client, &email, &base_url.0, &subscription_token)
         .await
-        .context("")?;
+        .context("Failed to send the confirmation email")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-fn generate_subcription_token() -> String {
+#[tracing::instrument(name = "Get pending subscriber by email", skip(transaction, email))]
+async fn get_pending_subscriber_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    list_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+    SELECT s.id FROM subscriptions s
+    JOIN list_subscriptions l ON l.subscriber_id = s.id
+    WHERE s.email = $1 AND l.list_id = $2 AND l.status = 'pending_confirmation'
+      AND s.status <> 'suppressed'
+    FOR UPDATE OF l
+        "#,
+        email.as_ref(),
+        list_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Expire every outstanding token of the subscriber for the list and store a new one.
+#[tracing::instrument(name = "Reissue subscription token", skip(transaction))]
+pub async fn reissue_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<String, StoreTokenError> {
+    sqlx::query!(
+        r#"
+    UPDATE subsciption_tokens
+    SET expires_at = now()
+    WHERE subscriber_id = $1 AND list_id = $2 AND used_at IS NULL AND expires_at > now()
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(StoreTokenError)?;
+
+    let subscription_token = generate_subcription_token();
+    store_token(transaction, subscriber_id, list_id, &subscription_token).await?;
+
+    Ok(subscription_token)
+}
+
+pub fn generate_subcription_token() -> String {
     let mut rng = thread_rng();
 
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
@@ -94,6 +277,15 @@ impl std::fmt::Debug for SubscribeError {
     }
 }
 
+impl From<ListError> for SubscribeError {
+    fn from(e: ListError) -> Self {
+        match e {
+            ListError::UnexpectedError(e) => SubscribeError::UnexpectedError(e.into()),
+            e => SubscribeError::ValidationError(e.to_string()),
+        }
+    }
+}
+
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
@@ -103,6 +295,12 @@ impl ResponseError for SubscribeError {
     }
 }
 
+/// What `insert_subscriber` found for the submitted email.
+pub enum InsertOutcome {
+    Inserted(Uuid),
+    Existing { subscriber_id: Uuid, status: String },
+}
+
 #[tracing::instrument(
     name = "saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -110,37 +308,73 @@ impl ResponseError for SubscribeError {
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<(Uuid), sqlx::Error> {
+) -> Result<InsertOutcome, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO NOTHING
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Unable to execute queryL: {:?}", e);
         e
-    })?;
-    Ok(subscriber_id)
+    })?
+    .rows_affected();
+    if inserted == 1 {
+        return Ok(InsertOutcome::Inserted(subscriber_id));
+    }
+
+    let existing = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(InsertOutcome::Existing {
+        subscriber_id: existing.id,
+        status: existing.status,
+    })
+}
+
+#[tracing::instrument(name = "Restart double opt-in", skip(transaction, new_subscriber))]
+async fn restart_double_opt_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET status = 'pending_confirmation', name = $2, subscribed_at = $3
+    WHERE id = $1
+        "#,
+        subscriber_id,
+        new_subscriber.name.as_ref(),
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "send confirmation email to subscriber",
-    skip(email_client, subscriber, base_url, subscription_token)
+    skip(email_client, subscriber_email, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    subscriber: NewSubscriber,
+    subscriber_email: &SubscriberEmail,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -157,8 +391,10 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
     email_client
-        .send_email(&subscriber.email, "Welcome!", &html_content, &text_content)
-        .await
+        .send_email(subscriber_email, "Welcome!", &html_content, &text_content)
+        .await?;
+
+    Ok(())
 }
 
 pub struct StoreTokenError(sqlx::Error);
@@ -208,15 +444,19 @@ pub fn error_chain_fmt(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-    INSERT INTO subsciption_tokens (subscriber_id, subscription_token)
-    VALUES ($1, $2)
+    INSERT INTO subsciption_tokens (subscriber_id, list_id, subscription_token, created_at, expires_at)
+    VALUES ($1, $2, $3, $4, $5)
         "#,
         subscriber_id,
+        list_id,
         subscription_token,
+        Utc::now(),
+        Utc::now() + Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS),
     )
     .execute(transaction)
     .await
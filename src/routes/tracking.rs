@@ -0,0 +1,291 @@
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(serde::Deserialize)]
+pub struct TrackingParameters {
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    /// The destination of a tracked link.
+    url: Option<String>,
+    tag: String,
+}
+
+impl TrackingParameters {
+    fn verify(&self, kind: &str, secret: &HmacSecret) -> Result<(), anyhow::Error> {
+        let tag = hex::decode(&self.tag)?;
+
+        let mac = tracking_mac(
+            kind,
+            self.issue_id,
+            self.subscriber_id,
+            self.url.as_deref(),
+            secret,
+        );
+        mac.verify_slice(&tag)?;
+
+        Ok(())
+    }
+}
+
+fn tracking_mac(
+    kind: &str,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: Option<&str>,
+    secret: &HmacSecret,
+) -> Hmac<sha2::Sha256> {
+    let mut message = format!(
+        "{}:issue_id={}&subscriber_id={}",
+        kind, issue_id, subscriber_id
+    );
+    if let Some(url) = url {
+        message.push_str("&url=");
+        message.push_str(url);
+    }
+
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    mac
+}
+
+fn tracking_tag(
+    kind: &str,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: Option<&str>,
+    secret: &HmacSecret,
+) -> String {
+    hex::encode(
+        tracking_mac(kind, issue_id, subscriber_id, url, secret)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Build the signed URL of the open tracking pixel of a recipient.
+pub fn open_tracking_link(
+    base_url: &str,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    secret: &HmacSecret,
+) -> String {
+    format!(
+        "{}/track/open?issue_id={}&subscriber_id={}&tag={}",
+        base_url,
+        issue_id,
+        subscriber_id,
+        tracking_tag("open", issue_id, subscriber_id, None, secret)
+    )
+}
+
+/// Build the signed link redirecting a recipient to `url` through the click
+/// tracker.
+pub fn click_tracking_link(
+    base_url: &str,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: &str,
+    secret: &HmacSecret,
+) -> String {
+    format!(
+        "{}/track/click?issue_id={}&subscriber_id={}&url={}&tag={}",
+        base_url,
+        issue_id,
+        subscriber_id,
+        urlencoding::encode(url),
+        tracking_tag("click", issue_id, subscriber_id, Some(url), secret)
+    )
+}
+
+/// Route the links of the HTML content of an email through the click tracker
+/// and add the open tracking pixel.
+///
+/// Only absolute `http(s)` links are rewritten: links to the newsletter's own
+/// pages, such as the unsubscribe link, are left alone.
+pub fn add_tracking(
+    html: &str,
+    base_url: &str,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    secret: &HmacSecret,
+) -> String {
+    let mut tracked = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("href=\"") {
+        let (before, after) = rest.split_at(start + "href=\"".len());
+        tracked.push_str(before);
+        let end = match after.find('"') {
+            Some(end) => end,
+            None => {
+                rest = after;
+                break;
+            }
+        };
+        let href = &after[..end];
+        match htmlescape::decode_html(href) {
+            Ok(url)
+                if (url.starts_with("http://") || url.starts_with("https://"))
+                    && !url.starts_with(base_url) =>
+            {
+                let link = click_tracking_link(base_url, issue_id, subscriber_id, &url, secret);
+                tracked.push_str(&htmlescape::encode_minimal(&link));
+            }
+            _ => tracked.push_str(href),
+        }
+        rest = &after[end..];
+    }
+    tracked.push_str(rest);
+
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="">"#,
+        htmlescape::encode_minimal(&open_tracking_link(
+            base_url,
+            issue_id,
+            subscriber_id,
+            secret
+        ))
+    );
+    match tracked.rfind("</body>") {
+        Some(end) => tracked.insert_str(end, &pixel),
+        None => tracked.push_str(&pixel),
+    }
+    tracked
+}
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The tracking link is invalid.")]
+    InvalidLink(#[source] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TrackingError::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// Serve the tracking pixel, recording that the recipient opened the issue.
+#[tracing::instrument(name = "Track an open", skip_all, fields(issue_id=%parameters.issue_id))]
+pub async fn track_open(
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    parameters
+        .verify("open", &secret)
+        .map_err(TrackingError::InvalidLink)?;
+
+    record_event(&pool, &parameters, "open").await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL))
+}
+
+/// Redirect the recipient to the destination of a tracked link, recording
+/// the click.
+#[tracing::instrument(name = "Track a click", skip_all, fields(issue_id=%parameters.issue_id))]
+pub async fn track_click(
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    parameters
+        .verify("click", &secret)
+        .map_err(TrackingError::InvalidLink)?;
+    let url = parameters
+        .url
+        .as_deref()
+        .ok_or_else(|| TrackingError::InvalidLink(anyhow::anyhow!("The URL is missing.")))?;
+
+    record_event(&pool, &parameters, "click").await;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
+
+/// Failing to record an event must not break the email for the recipient, so
+/// errors are only logged. Nothing is recorded for issues without tracking or
+/// for subscribers that have since been deleted.
+async fn record_event(pool: &PgPool, parameters: &TrackingParameters, kind: &str) {
+    let recorded = sqlx::query!(
+        r#"
+        INSERT INTO issue_events (event_id, newsletter_issue_id, subscriber_id, kind, url, occurred_at)
+        SELECT $1, i.newsletter_issue_id, s.id, $4, $5, now()
+        FROM newsletter_issues i, subscriptions s
+        WHERE i.newsletter_issue_id = $2 AND i.tracking AND s.id = $3
+        "#,
+        Uuid::new_v4(),
+        parameters.issue_id,
+        parameters.subscriber_id,
+        kind,
+        parameters.url
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a tracking event.");
+    if let Err(e) = recorded {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record a tracking event.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::add_tracking;
+    use crate::startup::HmacSecret;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    #[test]
+    fn external_links_are_rewritten_and_a_pixel_is_added() {
+        let secret = HmacSecret(Secret::new("secret".into()));
+        let html = concat!(
+            r#"<html><body><a href="https://example.com/?a=1&amp;b=2">Post</a> "#,
+            r#"<a href="mailto:hi@example.com">Mail</a> "#,
+            r#"<a href="https://news.example.com/subscriptions/unsubscribe?x=1">Leave</a>"#,
+            "</body></html>"
+        );
+
+        let tracked = add_tracking(
+            html,
+            "https://news.example.com",
+            Uuid::nil(),
+            Uuid::nil(),
+            &secret,
+        );
+
+        assert!(tracked.contains(r#"<a href="https://news.example.com/track/click?issue_id="#));
+        assert!(tracked.contains("&amp;url=https%3A%2F%2Fexample.com%2F%3Fa%3D1%26b%3D2&amp;tag="));
+        assert!(tracked.contains(r#"<a href="mailto:hi@example.com">"#));
+        assert!(tracked
+            .contains(r#"<a href="https://news.example.com/subscriptions/unsubscribe?x=1">"#));
+        assert!(tracked.ends_with(r#"width="1" height="1" alt=""></body></html>"#));
+        assert!(tracked.contains(r#"<img src="https://news.example.com/track/open?issue_id="#));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::mailing_lists::unsubscribe_from_all_lists;
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    tag: String,
+}
+
+impl UnsubscribeParameters {
+    fn verify(&self, secret: &HmacSecret) -> Result<Uuid, anyhow::Error> {
+        let tag = hex::decode(&self.tag)?;
+
+        let mac = unsubscribe_mac(self.subscriber_id, secret);
+        mac.verify_slice(&tag)?;
+
+        Ok(self.subscriber_id)
+    }
+}
+
+fn unsubscribe_mac(subscriber_id: Uuid, secret: &HmacSecret) -> Hmac<sha2::Sha256> {
+    let query_string = format!("subscriber_id={}", subscriber_id);
+
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(query_string.as_bytes());
+    mac
+}
+
+/// Build the signed one-click unsubscribe link for a subscriber.
+pub fn unsubscribe_link(base_url: &str, subscriber_id: Uuid, secret: &HmacSecret) -> String {
+    let tag = hex::encode(
+        unsubscribe_mac(subscriber_id, secret)
+            .finalize()
+            .into_bytes(),
+    );
+
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&tag={}",
+        base_url, subscriber_id, tag
+    )
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = parameters
+        .verify(&secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <form action="/subscriptions/unsubscribe?subscriber_id={subscriber_id}&tag={tag}" method="post">
+        <p>Do you want to stop receiving our newsletter?</p>
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            tag = parameters.tag,
+        )))
+}
+
+/// Handles both the button on the unsubscribe page and RFC 8058 one-click
+/// requests, which POST `List-Unsubscribe=One-Click` to the signed link.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, secret))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = parameters
+        .verify(&secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+
+    mark_subscriber_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .context("Failed to mark the subscriber as unsubscribed.")?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>You have been unsubscribed.</p>
+</body>
+</html>"#,
+    ))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    unsubscribe_from_all_lists(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use crate::domain::SubscriberEmail;
+use crate::routes::error_chain_fmt;
+use crate::startup::WebhookSecret;
+use actix_web::http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Bounce types after which an address is not worth emailing again.
+const HARD_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress"];
+
+/// The fields we use from Postmark's bounce, spam complaint and delivery
+/// webhooks. Bounces and complaints carry the address in `Email`, deliveries
+/// in `Recipient`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    email: Option<String>,
+    recipient: Option<String>,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+}
+
+impl PostmarkEvent {
+    /// Hard bounces and spam complaints suppress the address.
+    fn suppresses(&self) -> bool {
+        match self.record_type.as_str() {
+            "Bounce" => self
+                .bounce_type
+                .as_deref()
+                .map_or(false, |t| HARD_BOUNCE_TYPES.contains(&t)),
+            "SpamComplaint" => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, header_value);
+                response
+            }
+            WebhookError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+/// Ingest a Postmark webhook. Postmark sends the shared secret as the
+/// password of HTTP basic authentication credentials set in the webhook URL.
+///
+/// Bounces, spam complaints and deliveries are recorded; hard bounces and
+/// complaints also suppress the address, so that no further issue is sent to
+/// it. Other record types are acknowledged and ignored.
+#[tracing::instrument(
+    name = "Ingest a Postmark webhook",
+    skip_all,
+    fields(record_type=tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    body: web::Bytes,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    secret: web::Data<WebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    let password =
+        basic_authentication_password(request.headers()).map_err(WebhookError::AuthError)?;
+    if !secrets_match(&password, &secret.0) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook secret."
+        )));
+    }
+
+    let payload = std::str::from_utf8(&body)
+        .context("The webhook body is not valid UTF-8.")
+        .map_err(WebhookError::ValidationError)?;
+    let event: PostmarkEvent = serde_json::from_str(payload)
+        .context("The webhook body is not a valid Postmark event.")
+        .map_err(WebhookError::ValidationError)?;
+    tracing::Span::current().record("record_type", &tracing::field::display(&event.record_type));
+
+    let email = match event.record_type.as_str() {
+        "Bounce" | "SpamComplaint" => event.email.as_deref(),
+        "Delivery" => event.recipient.as_deref(),
+        _ => return Ok(HttpResponse::Ok().finish()),
+    }
+    .context("The webhook does not say which address it is about.")
+    .map_err(WebhookError::ValidationError)?;
+    let email = SubscriberEmail::parse(email.to_owned())
+        .map_err(|e| WebhookError::ValidationError(anyhow::anyhow!(e)))?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    record_email_event(&mut transaction, &event, &email, payload)
+        .await
+        .context("Failed to record an email event")?;
+    if event.suppresses() {
+        suppress_address(&mut transaction, &email)
+            .await
+            .context("Failed to suppress an email address")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit an email event")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Extract the password of an `Authorization: Basic <credentials>` header.
+fn basic_authentication_password(headers: &HeaderMap) -> Result<Secret<String>, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("missing Authorization header")?
+        .to_str()
+        .context("Authorization header was not properly utf-8 encoded")?;
+    let encoded = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded = base64::decode_config(encoded, base64::STANDARD)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded =
+        String::from_utf8(decoded).context("The decoded credentials are not valid UTF-8.")?;
+    let (_, password) = decoded
+        .split_once(':')
+        .context("The 'Basic' credentials have no password.")?;
+
+    Ok(Secret::new(password.to_owned()))
+}
+
+/// Comparing digests rather than the secrets themselves keeps the time taken
+/// independent of how much of the secret was guessed right.
+fn secrets_match(candidate: &Secret<String>, expected: &Secret<String>) -> bool {
+    Sha256::digest(candidate.expose_secret().as_bytes())
+        == Sha256::digest(expected.expose_secret().as_bytes())
+}
+
+#[tracing::instrument(skip(transaction, event, payload))]
+async fn record_email_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &PostmarkEvent,
+    email: &SubscriberEmail,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (event_id, record_type, email, message_id, details, payload, received_at)
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        event.record_type,
+        email.as_ref(),
+        event.message_id,
+        event.bounce_type,
+        payload
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Suppressed subscribers are no longer confirmed, so no new deliveries are
+/// queued for them and the pending ones are skipped.
+#[tracing::instrument(skip(transaction))]
+async fn suppress_address(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'suppressed' WHERE email = $1"#,
+        email.as_ref()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! A small filter language to send an issue to part of its lists only.
+//!
+//! A segment is a whitespace-separated list of conditions, all of which must
+//! hold for a subscriber to receive the issue:
+//!
+//! - `tag:vip`: the subscriber has the `vip` tag;
+//! - `-tag:vip`: the subscriber does not have the `vip` tag;
+//! - `subscribed_after:2022-01-01`: subscribed on or after that day (UTC);
+//! - `subscribed_before:2022-07-01`: subscribed before that day (UTC);
+//! - `attr.country:FR`: the `country` attribute of the subscriber is `FR`.
+//!
+//! Values containing spaces can be quoted: `attr.city:"New York"`.
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt;
+
+const MAX_NAME_LENGTH: usize = 50;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Segment {
+    pub tags: Vec<String>,
+    pub excluded_tags: Vec<String>,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        let mut segment = Segment::default();
+        for term in tokenize(s)? {
+            let (key, value) = term
+                .split_once(':')
+                .ok_or_else(|| format!("`{}` is not a valid condition.", term))?;
+            match key {
+                "tag" => push_unique(&mut segment.tags, parse_tag(value)?),
+                "-tag" => push_unique(&mut segment.excluded_tags, parse_tag(value)?),
+                "subscribed_after" => segment.subscribed_from = Some(parse_day(value)?),
+                "subscribed_before" => segment.subscribed_before = Some(parse_day(value)?),
+                _ => match key.strip_prefix("attr.") {
+                    Some(name) => {
+                        let name = parse_attribute_name(name)?;
+                        match segment.attributes.iter().find(|(n, _)| *n == name) {
+                            Some((_, v)) if v != value => {
+                                return Err(format!(
+                                    "The `{}` attribute cannot be both `{}` and `{}`.",
+                                    name, v, value
+                                ))
+                            }
+                            Some(_) => {}
+                            None => segment.attributes.push((name, value.to_owned())),
+                        }
+                    }
+                    None => return Err(format!("`{}` is not a known condition.", key)),
+                },
+            }
+        }
+        Ok(segment)
+    }
+
+    /// An empty segment matches every subscriber.
+    pub fn is_empty(&self) -> bool {
+        *self == Segment::default()
+    }
+
+    /// The attribute names and values as two parallel arrays, the way the
+    /// delivery query expects them.
+    pub fn attribute_arrays(&self) -> (Vec<String>, Vec<String>) {
+        self.attributes.iter().cloned().unzip()
+    }
+}
+
+/// The canonical form of the segment, which is what gets stored with an issue.
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = Vec::new();
+        terms.extend(self.tags.iter().map(|t| format!("tag:{}", t)));
+        terms.extend(self.excluded_tags.iter().map(|t| format!("-tag:{}", t)));
+        if let Some(day) = self.subscribed_from {
+            terms.push(format!("subscribed_after:{}", day.format("%Y-%m-%d")));
+        }
+        if let Some(day) = self.subscribed_before {
+            terms.push(format!("subscribed_before:{}", day.format("%Y-%m-%d")));
+        }
+        for (name, value) in &self.attributes {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                terms.push(format!("attr.{}:\"{}\"", name, value));
+            } else {
+                terms.push(format!("attr.{}:{}", name, value));
+            }
+        }
+        write!(f, "{}", terms.join(" "))
+    }
+}
+
+/// Tags are lowercase so that `VIP` and `vip` are the same tag.
+pub fn parse_tag(s: &str) -> Result<String, String> {
+    let tag = s.trim().to_lowercase();
+    if is_valid_name(&tag) {
+        Ok(tag)
+    } else {
+        Err(format!(
+            "`{}` is not a valid tag: use up to {} letters, digits, dashes or underscores.",
+            s, MAX_NAME_LENGTH
+        ))
+    }
+}
+
+pub fn parse_attribute_name(s: &str) -> Result<String, String> {
+    let name = s.trim();
+    if is_valid_name(name) {
+        Ok(name.to_owned())
+    } else {
+        Err(format!(
+            "`{}` is not a valid attribute name: use up to {} letters, digits, dashes or underscores.",
+            s, MAX_NAME_LENGTH
+        ))
+    }
+}
+
+fn is_valid_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= MAX_NAME_LENGTH
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse_day(s: &str) -> Result<DateTime<Utc>, String> {
+    let day = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("`{}` is not a valid date, expected YYYY-MM-DD.", s))?;
+    Ok(DateTime::from_utc(day.and_hms(0, 0, 0), Utc))
+}
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+/// Split on whitespace, keeping double-quoted sections (without their quotes)
+/// together.
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in s.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("The segment has an unterminated quote.".into());
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    #[test]
+    fn an_empty_segment_matches_everybody() {
+        let segment = Segment::parse("  ").unwrap();
+
+        assert!(segment.is_empty());
+        assert_eq!(segment.to_string(), "");
+    }
+
+    #[test]
+    fn conditions_are_parsed() {
+        let segment = Segment::parse(
+            r#"tag:VIP -tag:churned tag:vip subscribed_after:2022-01-01 attr.city:"New York""#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            segment,
+            Segment {
+                tags: vec!["vip".into()],
+                excluded_tags: vec!["churned".into()],
+                subscribed_from: Some(Utc.ymd(2022, 1, 1).and_hms(0, 0, 0)),
+                subscribed_before: None,
+                attributes: vec![("city".into(), "New York".into())],
+            }
+        );
+    }
+
+    #[test]
+    fn the_canonical_form_parses_back_to_the_same_segment() {
+        let segment = Segment::parse(
+            r#"attr.city:"New York" subscribed_before:2022-07-01 -tag:churned tag:vip"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            segment.to_string(),
+            r#"tag:vip -tag:churned subscribed_before:2022-07-01 attr.city:"New York""#
+        );
+        assert_eq!(Segment::parse(&segment.to_string()).unwrap(), segment);
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for segment in [
+            "vip",
+            "colour:red",
+            "tag:",
+            "tag:not/a/tag",
+            "subscribed_after:yesterday",
+            "attr.:x",
+            "attr.city:Paris attr.city:Rome",
+            r#"attr.city:"New York"#,
+        ] {
+            assert_err!(Segment::parse(segment), "{} was accepted", segment);
+        }
+    }
+}
@@ -7,8 +7,17 @@ use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
+/// The password of the user has been checked, but not their second factor
+/// yet: they are not logged in.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TwoFactorPending {
+    pub user_id: Uuid,
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_VERSION_KEY: &'static str = "session_version";
+    const TWO_FACTOR_PENDING_KEY: &'static str = "two_factor_pending";
 
     pub fn renew(&self) {
         self.0.renew()
@@ -22,6 +31,35 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Drop the login, if any, but keep the rest of the session.
+    pub fn remove_user_id(&self) {
+        self.0.remove(Self::USER_ID_KEY);
+        self.0.remove(Self::SESSION_VERSION_KEY);
+    }
+
+    pub fn insert_session_version(&self, version: i32) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::SESSION_VERSION_KEY, version)
+    }
+
+    pub fn get_session_version(&self) -> Result<Option<i32>, serde_json::Error> {
+        self.0.get(Self::SESSION_VERSION_KEY)
+    }
+
+    pub fn insert_two_factor_pending(
+        &self,
+        pending: &TwoFactorPending,
+    ) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::TWO_FACTOR_PENDING_KEY, pending)
+    }
+
+    pub fn get_two_factor_pending(&self) -> Result<Option<TwoFactorPending>, serde_json::Error> {
+        self.0.get(Self::TWO_FACTOR_PENDING_KEY)
+    }
+
+    pub fn remove_two_factor_pending(&self) {
+        self.0.remove(Self::TWO_FACTOR_PENDING_KEY);
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
@@ -1,12 +1,25 @@
 use std::net::TcpListener;
 
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{reject_anonymous_users, TotpKey};
 use crate::configuration::{DBSettings, Settings};
 use crate::routes::confirm;
+use crate::routes::{
+    accept_invite, archive, archive_feed, archived_issue, confirm_subscriber_manually,
+    confirm_totp, create_draft, create_list, create_token, delete_draft, delete_subscriber,
+    delete_user, disable_user, edit_draft_form, enable_user, export_subscribers, forgot_password,
+    forgot_password_form, import_subscribers, import_subscribers_form, invite_form, invite_user,
+    list_api_tokens, list_drafts, list_lists, list_subscribers, list_users, postmark_webhook,
+    preview_draft, publish_draft, publish_newsletter_form, publish_newsletter_issue, remove_totp,
+    resend_confirmation, reset_password, reset_password_form, revoke_token, security_settings,
+    send_test_email, set_issue_tracking, set_up_totp, setup, setup_form, subscriber_details,
+    track_click, track_open, two_factor, two_factor_form, unsubscribe_subscriber, update_draft,
+    update_subscriber_tags,
+};
 use crate::{
-    email_client::EmailClient, routes::admin_dashboard, routes::change_password,
-    routes::get_change_password_form, routes::health_check, routes::home, routes::login,
-    routes::login_form, routes::logout, routes::publish_newsletter, routes::subscribe,
+    email_client::EmailClient, routes::admin_dashboard, routes::cancel_newsletter_issue,
+    routes::change_password, routes::get_change_password_form, routes::health_check, routes::home,
+    routes::login, routes::login_form, routes::logout, routes::newsletter_issue_status,
+    routes::publish_newsletter, routes::subscribe, routes::unsubscribe, routes::unsubscribe_form,
 };
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -31,17 +44,9 @@ impl Application {
             .connect_timeout(std::time::Duration::from_secs(2))
             .connect_lazy_with(config.database.with_db());
 
-        let sender_email = config
-            .email_client
-            .sender()
-            .expect("Invalid sender email address from config");
-        let timeout = config.email_client.timeout();
-        let email_client = EmailClient::new(
-            config.email_client.base_url,
-            sender_email,
-            config.email_client.authorization_token,
-            timeout,
-        );
+        let webhook_secret = config.email_client.webhook_secret.clone();
+        let totp_key = TotpKey::parse(&config.application.totp_encryption_key)?;
+        let email_client = config.email_client.client();
 
         let address = format!("{}:{}", config.application.host, config.application.port);
         let listener = TcpListener::bind(address)?;
@@ -53,6 +58,8 @@ impl Application {
             email_client,
             config.application.base_url,
             config.application.hmac_secret,
+            webhook_secret,
+            totp_key,
             config.redis_uri,
         )
         .await?;
@@ -74,15 +81,22 @@ pub struct ApplicationBaseUrl(pub String);
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
+#[derive(Clone)]
+pub struct WebhookSecret(pub Secret<String>);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    webhook_secret: Secret<String>,
+    totp_key: TotpKey,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let db = web::Data::new(db);
+    let totp_key = web::Data::new(totp_key);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
@@ -99,23 +113,134 @@ pub async fn run(
             .wrap(TracingLogger::default())
             .route("/login", web::post().to(login))
             .route("/login", web::get().to(login_form))
+            .route("/login/two_factor", web::get().to(two_factor_form))
+            .route("/login/two_factor", web::post().to(two_factor))
+            .route("/password_reset", web::get().to(forgot_password_form))
+            .route("/password_reset", web::post().to(forgot_password))
+            .route(
+                "/password_reset/confirm",
+                web::get().to(reset_password_form),
+            )
+            .route("/password_reset/confirm", web::post().to(reset_password))
             .route("/", web::get().to(home))
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletter))
+            .route(
+                "/subscriptions/resend_confirmation",
+                web::post().to(resend_confirmation),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/api/v1/newsletters", web::post().to(publish_newsletter))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/feed.xml", web::get().to(archive_feed))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/track/open", web::get().to(track_open))
+            .route("/track/click", web::get().to(track_click))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .route("/invite", web::get().to(invite_form))
+            .route("/invite", web::post().to(accept_invite))
+            .route("/setup", web::get().to(setup_form))
+            .route("/setup", web::post().to(setup))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::post().to(change_password))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/newsletter", web::get().to(publish_newsletter_form))
+                    .route("/newsletter", web::post().to(publish_newsletter_issue))
                     .route("/password", web::get().to(get_change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(logout)),
+                    .route("/logout", web::post().to(logout))
+                    .route("/security", web::get().to(security_settings))
+                    .route("/security/totp", web::post().to(set_up_totp))
+                    .route("/security/totp/confirm", web::post().to(confirm_totp))
+                    .route("/security/totp/disable", web::post().to(remove_totp))
+                    .route("/lists", web::get().to(list_lists))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/tags",
+                        web::post().to(update_subscriber_tags),
+                    )
+                    .route("/tokens", web::get().to(list_api_tokens))
+                    .route("/tokens", web::post().to(create_token))
+                    .route("/tokens/{token_id}/revoke", web::post().to(revoke_token))
+                    .route("/users", web::get().to(list_users))
+                    .route("/users/invite", web::post().to(invite_user))
+                    .route("/users/{user_id}/disable", web::post().to(disable_user))
+                    .route("/users/{user_id}/enable", web::post().to(enable_user))
+                    .route("/users/{user_id}/delete", web::post().to(delete_user))
+                    .route("/newsletters/drafts", web::get().to(list_drafts))
+                    .route("/newsletters/drafts", web::post().to(create_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::post().to(update_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/delete",
+                        web::post().to(delete_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/preview",
+                        web::get().to(preview_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/test",
+                        web::post().to(send_test_email),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(newsletter_issue_status),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/cancel",
+                        web::post().to(cancel_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/tracking",
+                        web::post().to(set_issue_tracking),
+                    ),
             )
             .app_data(db.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(web::Data::new(WebhookSecret(webhook_secret.clone())))
+            .app_data(totp_key.clone())
         // .app_data()
     })
     .listen(listener)?
@@ -0,0 +1,180 @@
+//! Merge fields in the content of an issue, filled in for every recipient.
+//!
+//! A merge field is written `{{ field }}`, with the following fields available:
+//!
+//! - `name`: the name of the subscriber;
+//! - `email`: the email address of the subscriber;
+//! - `unsubscribe_url`: the one-click unsubscribe link of the subscriber;
+//! - `confirm_date`: the day (`YYYY-MM-DD`) the subscriber confirmed their subscription.
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    ConfirmDate,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Field> {
+        match s {
+            "name" => Some(Field::Name),
+            "email" => Some(Field::Email),
+            "unsubscribe_url" => Some(Field::UnsubscribeUrl),
+            "confirm_date" => Some(Field::ConfirmDate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template(Vec<Part>);
+
+/// The values substituted for the merge fields for one recipient.
+pub struct MergeFields<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or("A merge field is missing its closing `}}`.")?;
+            let name = after[..end].trim();
+            let field = Field::parse(name).ok_or_else(|| {
+                format!(
+                    "`{{{{ {} }}}}` is not a known merge field. Use name, email, unsubscribe_url or confirm_date.",
+                    name
+                )
+            })?;
+            parts.push(Part::Field(field));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Ok(Template(parts))
+    }
+
+    /// Fill in the merge fields, escaping their values for HTML if `html` is set.
+    pub fn render(&self, fields: &MergeFields, html: bool) -> String {
+        let mut rendered = String::new();
+        for part in &self.0 {
+            let value = match part {
+                Part::Text(text) => {
+                    rendered.push_str(text);
+                    continue;
+                }
+                Part::Field(Field::Name) => fields.name.to_owned(),
+                Part::Field(Field::Email) => fields.email.to_owned(),
+                Part::Field(Field::UnsubscribeUrl) => fields.unsubscribe_url.to_owned(),
+                Part::Field(Field::ConfirmDate) => fields
+                    .confirmed_at
+                    .map(|d| d.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+            };
+            if html {
+                rendered.push_str(&htmlescape::encode_minimal(&value));
+            } else {
+                rendered.push_str(&value);
+            }
+        }
+        rendered
+    }
+}
+
+/// The HTML and plain text templates of an issue.
+pub struct IssueTemplate {
+    html: Template,
+    text: Template,
+}
+
+impl IssueTemplate {
+    pub fn parse(html_content: &str, text_content: &str) -> Result<IssueTemplate, String> {
+        let html = Template::parse(html_content)
+            .map_err(|e| format!("The HTML content is invalid: {}", e))?;
+        let text = Template::parse(text_content)
+            .map_err(|e| format!("The plain text content is invalid: {}", e))?;
+        Ok(IssueTemplate { html, text })
+    }
+
+    /// Returns the `(html, text)` content for one recipient.
+    pub fn render(&self, fields: &MergeFields) -> (String, String) {
+        (
+            self.html.render(fields, true),
+            self.text.render(fields, false),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeFields, Template};
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    fn fields() -> MergeFields<'static> {
+        MergeFields {
+            name: "Ursula <Le Guin>",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?a=1&b=2",
+            confirmed_at: Some(Utc.ymd(2022, 7, 1).and_hms(9, 0, 0)),
+        }
+    }
+
+    #[test]
+    fn merge_fields_are_filled_in() {
+        let template =
+            Template::parse("Hi {{name}}, since {{ confirm_date }}: {{ unsubscribe_url }}")
+                .unwrap();
+
+        assert_eq!(
+            template.render(&fields(), false),
+            "Hi Ursula <Le Guin>, since 2022-07-01: https://example.com/unsubscribe?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn merge_fields_are_escaped_in_html() {
+        let template = Template::parse("<p>Hi {{ name }}</p>").unwrap();
+
+        assert_eq!(
+            template.render(&fields(), true),
+            "<p>Hi Ursula &lt;Le Guin&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn content_without_merge_fields_is_unchanged() {
+        let content = "<p>Plain {content} with }} braces</p>";
+
+        assert_eq!(
+            Template::parse(content).unwrap().render(&fields(), true),
+            content
+        );
+    }
+
+    #[test]
+    fn unknown_or_unclosed_merge_fields_are_rejected() {
+        assert_err!(Template::parse("Hi {{ nmae }}"));
+        assert_err!(Template::parse("Hi {{ name"));
+        assert_err!(Template::parse("Hi {{}}"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+fn api_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+/// Create a token through the admin UI and return its plaintext value.
+async fn create_token(app: &TestApp, name: &str) -> String {
+    let html_page = app.post_create_api_token(name).await.text().await.unwrap();
+    let start =
+        html_page.find(r#"<code id="api-token">"#).unwrap() + r#"<code id="api-token">"#.len();
+    let end = start + html_page[start..].find("</code>").unwrap();
+
+    html_page[start..end].to_owned()
+}
+
+async fn get_token_id(app: &TestApp, name: &str) -> Uuid {
+    sqlx::query!("SELECT token_id FROM api_tokens WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_api_tokens() {
+    let app = spawn_app().await;
+
+    let resp = app.post_create_api_token("ci").await;
+
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn new_tokens_are_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let token = create_token(&app, "ci").await;
+
+    let token_hash = sqlx::query!("SELECT token_hash FROM api_tokens WHERE name = 'ci'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_hash;
+    assert_ne!(token_hash, token);
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<td>ci</td>"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn new_tokens_can_publish_through_the_api() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, "ci").await;
+
+    let resp = app
+        .post_api_newsletters(&token, &Uuid::new_v4().to_string(), &api_request_body())
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, "ci").await;
+    let token_id = get_token_id(&app, "ci").await;
+
+    let resp = app.post_revoke_api_token(&token_id).await;
+    assert_is_redirect_to(&resp, "/admin/tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>The token has been revoked.</i></p>"));
+
+    let resp = app
+        .post_api_newsletters(&token, &Uuid::new_v4().to_string(), &api_request_body())
+        .await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn users_cannot_revoke_the_tokens_of_other_users() {
+    let app = spawn_app().await;
+    let other_user_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, 'someone-else', 'not-a-hash', 'owner')",
+        other_user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let token = zero2prod::authentication::create_api_token(&app.db_pool, other_user_id, "theirs")
+        .await
+        .unwrap();
+    let token_id = get_token_id(&app, "theirs").await;
+    app.test_user.login(&app).await;
+
+    app.post_revoke_api_token(&token_id).await;
+
+    let resp = app
+        .post_api_newsletters(
+            secrecy::ExposeSecret::expose_secret(&token),
+            &Uuid::new_v4().to_string(),
+            &api_request_body(),
+        )
+        .await;
+    assert_eq!(resp.status().as_u16(), 202);
+}
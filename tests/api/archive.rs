@@ -0,0 +1,237 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Publish an issue through the API and return its archive slug.
+async fn publish_issue(app: &TestApp, title: &str, extra: serde_json::Value) -> String {
+    let mut body = serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Hi {{ name }}, this is the body.",
+            "html": "<p>Hi {{ name }}, this is the body.</p>",
+        }
+    });
+    body.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let response: serde_json::Value = response.json().await.unwrap();
+    let issue_id = Uuid::parse_str(response["newsletter_issue_id"].as_str().unwrap()).unwrap();
+
+    sqlx::query!(
+        "SELECT slug FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .slug
+    .unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_in_the_archive() {
+    let app = spawn_app().await;
+
+    let slug = publish_issue(&app, "Summer news!", serde_json::json!({})).await;
+
+    assert!(slug.starts_with("summer-news-"));
+    let html = app.get_archive("").await.text().await.unwrap();
+    assert!(html.contains(&format!(r#"<a href="/archive/{}">Summer news!</a>"#, slug)));
+}
+
+#[tokio::test]
+async fn an_archived_issue_is_rendered_without_personal_merge_fields() {
+    let app = spawn_app().await;
+    let slug = publish_issue(&app, "Summer news", serde_json::json!({})).await;
+
+    let response = app.get_archive(&format!("/{}", slug)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Summer news</h1>"));
+    assert!(html.contains("<p>Hi , this is the body.</p>"));
+}
+
+#[tokio::test]
+async fn scripts_are_stripped_from_archived_issues() {
+    let app = spawn_app().await;
+    let slug = publish_issue(
+        &app,
+        "Summer news",
+        serde_json::json!({
+            "content": {
+                "text": "The body.",
+                "html": "<p onclick=\"steal()\">The body.</p><script>steal()</script>",
+            }
+        }),
+    )
+    .await;
+
+    let html = app
+        .get_archive(&format!("/{}", slug))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p>The body.</p>"));
+    assert!(!html.contains("steal()"));
+
+    let feed = app.get_archive("/feed.xml").await.text().await.unwrap();
+    assert!(feed.contains("The body."));
+    assert!(!feed.contains("steal()"));
+}
+
+#[tokio::test]
+async fn archived_issues_leave_the_unsubscribe_link_out() {
+    let app = spawn_app().await;
+    let slug = publish_issue(
+        &app,
+        "Summer news",
+        serde_json::json!({
+            "content": {
+                "text": "Unsubscribe: {{ unsubscribe_url }}",
+                "html": "<p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a></p>",
+            }
+        }),
+    )
+    .await;
+
+    let html = app
+        .get_archive(&format!("/{}", slug))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Unsubscribe</a></p>"));
+    assert!(!html.contains("href=\"\""));
+    assert!(!html.contains("/subscriptions/unsubscribe"));
+}
+
+#[tokio::test]
+async fn an_unknown_slug_is_not_found() {
+    let app = spawn_app().await;
+
+    let response = app.get_archive("/no-such-issue-12345678").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn private_scheduled_and_cancelled_issues_are_not_public() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let private = publish_issue(&app, "Private", serde_json::json!({ "private": true })).await;
+    let scheduled = publish_issue(
+        &app,
+        "Scheduled",
+        serde_json::json!({ "publish_at": "2100-01-01T00:00:00Z" }),
+    )
+    .await;
+    let cancelled = publish_issue(
+        &app,
+        "Cancelled",
+        serde_json::json!({ "publish_at": "2100-01-01T00:00:00Z" }),
+    )
+    .await;
+    let cancelled_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE slug = $1",
+        cancelled
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    app.post_cancel_newsletter_issue(&cancelled_id).await;
+
+    let html = app.get_archive("").await.text().await.unwrap();
+    let feed = app.get_archive("/feed.xml").await.text().await.unwrap();
+    for slug in [private, scheduled, cancelled] {
+        assert!(!html.contains(&slug));
+        assert!(!feed.contains(&slug));
+        let response = app.get_archive(&format!("/{}", slug)).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn the_private_flag_can_be_set_from_the_publish_form() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Members only",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "private": "true",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let html = app.get_archive("").await.text().await.unwrap();
+    assert!(!html.contains("Members only"));
+}
+
+#[tokio::test]
+async fn the_feed_lists_public_issues() {
+    let app = spawn_app().await;
+    let slug = publish_issue(&app, "Summer <news>", serde_json::json!({})).await;
+
+    let response = app.get_archive("/feed.xml").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.starts_with(r#"<?xml version="1.0" encoding="utf-8"?>"#));
+    assert!(feed.contains("<title>Summer &lt;news&gt;</title>"));
+    assert!(feed.contains(&format!(
+        r#"<link href="{}/archive/{}"/>"#,
+        app.base_url, slug
+    )));
+    assert!(feed.contains("&lt;p&gt;Hi , this is the body.&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn sent_emails_link_to_the_archived_issue_unless_it_is_private() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1..)
+        .mount(&app.email_server)
+        .await;
+
+    let slug = publish_issue(&app, "Public", serde_json::json!({})).await;
+    publish_issue(&app, "Private", serde_json::json!({ "private": true })).await;
+    app.dispatch_all_pending_emails().await;
+
+    let link = format!("{}/archive/{}", app.base_url, slug);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let emails: Vec<serde_json::Value> = requests
+        .iter()
+        .flat_map(|r| {
+            let batch: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            batch.as_array().unwrap().clone()
+        })
+        .collect();
+    let public = emails.iter().find(|e| e["Subject"] == "Public").unwrap();
+    let private = emails.iter().find(|e| e["Subject"] == "Private").unwrap();
+    assert!(public["HtmlBody"].as_str().unwrap().starts_with(&format!(
+        r#"<p><a href="{}">View this email in your browser</a></p>"#,
+        link
+    )));
+    assert!(public["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("View this email in your browser: {}\n\n", link)));
+    assert!(!private["HtmlBody"].as_str().unwrap().contains("/archive/"));
+    assert!(!private["TextBody"].as_str().unwrap().contains("/archive/"));
+}
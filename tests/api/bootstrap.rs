@@ -0,0 +1,167 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use secrecy::{ExposeSecret, Secret};
+use zero2prod::bootstrap::{bootstrap, Bootstrap};
+use zero2prod::configuration::BootstrapSettings;
+
+/// Make the database look like a fresh installation.
+async fn delete_all_users(app: &TestApp) {
+    sqlx::query!("DELETE FROM api_tokens")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn setup_token(app: &TestApp) -> String {
+    match bootstrap(&app.db_pool, None).await.unwrap() {
+        Bootstrap::SetupPending(token) => token.expose_secret().to_owned(),
+        _ => panic!("Expected a setup token"),
+    }
+}
+
+async fn post_setup(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/setup?token={}", &app.address, token))
+        .form(&serde_json::json!({
+            "username": "first-owner",
+            "password": "a-strong-password",
+            "password_check": "a-strong-password",
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn login_as_first_owner(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": "first-owner",
+        "password": password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn nothing_is_bootstrapped_when_there_are_users() {
+    let app = spawn_app().await;
+
+    let outcome = bootstrap(&app.db_pool, None).await.unwrap();
+
+    assert!(matches!(outcome, Bootstrap::NotNeeded));
+}
+
+#[tokio::test]
+async fn the_first_owner_is_created_from_the_bootstrap_settings() {
+    let app = spawn_app().await;
+    delete_all_users(&app).await;
+    let settings = BootstrapSettings {
+        username: "first-owner".into(),
+        password: Secret::new("from-the-environment".into()),
+        email: None,
+    };
+
+    let outcome = bootstrap(&app.db_pool, Some(&settings)).await.unwrap();
+
+    assert!(matches!(outcome, Bootstrap::OwnerCreated(_)));
+    let resp = login_as_first_owner(&app, "from-the-environment").await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let role = sqlx::query!("SELECT role FROM users WHERE username = 'first-owner'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "owner");
+}
+
+#[tokio::test]
+async fn the_setup_page_creates_the_first_owner_once() {
+    let app = spawn_app().await;
+    delete_all_users(&app).await;
+    let token = setup_token(&app).await;
+
+    let resp = app
+        .api_client
+        .get(format!("{}/setup?token={}", &app.address, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let resp = post_setup(&app, &token).await;
+    assert_is_redirect_to(&resp, "/login");
+    let resp = login_as_first_owner(&app, "a-strong-password").await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+
+    let resp = post_setup(&app, &token).await;
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_setup_page_requires_the_logged_token() {
+    let app = spawn_app().await;
+    delete_all_users(&app).await;
+    setup_token(&app).await;
+
+    let resp = post_setup(&app, "not-the-token").await;
+
+    assert_eq!(resp.status().as_u16(), 401);
+    let n_users = sqlx::query!(r#"SELECT count(*) AS "n!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn the_seeded_admin_must_change_their_password() {
+    let app = spawn_app().await;
+
+    let must_change_password =
+        sqlx::query!("SELECT must_change_password FROM users WHERE username = 'admin'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .must_change_password;
+
+    assert!(must_change_password);
+}
+
+#[tokio::test]
+async fn users_who_must_change_their_password_are_sent_to_do_it() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET must_change_password = true WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>You must change your password before going further.</i></p>"));
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/admin/password");
+
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let resp = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/password");
+
+    let resp = app.get_admin_dashboard().await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
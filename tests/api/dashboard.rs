@@ -9,6 +9,7 @@ async fn must_logged_in_when_access_the_admin_dashboard() {
     assert_is_redirect_to(&resp, "/login");
 }
 
+#[tokio::test]
 async fn logout_clears_session_state() {
     let app = spawn_app().await;
 
@@ -0,0 +1,234 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+/// Create a draft as the logged-in test user and return its id.
+async fn create_draft(app: &TestApp) -> Uuid {
+    let resp = app.post_create_draft(&draft_body()).await;
+    assert_eq!(resp.status().as_u16(), 303);
+    let location = resp.headers()["Location"].to_str().unwrap();
+
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    app.insert_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+}
+
+async fn count_pending_tasks(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let resp = app.post_create_draft(&draft_body()).await;
+
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn saved_drafts_are_listed_but_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<li><a href="/admin/newsletters/drafts/{}">Newsletter title</a></li>"#,
+        draft_id
+    )));
+    assert_eq!(count_pending_tasks(&app).await, 0);
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    let resp = app
+        .post_draft_action(
+            &draft_id,
+            "",
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="A&#x20;better&#x20;title""#));
+    assert!(html_page.contains("&lt;p&gt;Newsletter body as HTML&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_previewed_as_html_and_plain_text() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    let resp = app.get_draft_preview(&draft_id, "html").await;
+    assert!(resp.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert_eq!(resp.headers()["Content-Security-Policy"], "sandbox");
+    assert_eq!(resp.text().await.unwrap(), "<p>Newsletter body as HTML</p>");
+
+    let resp = app.get_draft_preview(&draft_id, "text").await;
+    assert!(resp.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    assert_eq!(resp.text().await.unwrap(), "Newsletter body as plain text");
+}
+
+#[tokio::test]
+async fn test_emails_are_only_sent_to_the_logged_in_user() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app
+        .post_draft_action(&draft_id, "test", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+    assert_eq!(body["Subject"], "[Test] Newsletter title");
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>A test email has been sent to admin@example.com.</i></p>"));
+    assert_eq!(count_pending_tasks(&app).await, 0);
+}
+
+#[tokio::test]
+async fn test_emails_require_the_user_to_have_an_email_address() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_draft_action(&draft_id, "test", &serde_json::json!({}))
+        .await;
+
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page
+        .contains("<p><i>Your account has no valid email address to send a test to.</i></p>"));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_enqueues_it_exactly_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    let publish_body = serde_json::json!({
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "publish_at": "",
+    });
+
+    let resp = app
+        .post_draft_action(&draft_id, "publish", &publish_body)
+        .await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/{}", draft_id));
+
+    let resp = app
+        .post_draft_action(&draft_id, "publish", &publish_body)
+        .await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/{}", draft_id));
+
+    assert_eq!(count_pending_tasks(&app).await, 1);
+    let status = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status;
+    assert_eq!(status, "published");
+    assert!(!app.get_drafts_html().await.contains(&draft_id.to_string()));
+}
+
+#[tokio::test]
+async fn published_issues_can_no_longer_be_edited_as_drafts() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    app.post_draft_action(
+        &draft_id,
+        "publish",
+        &serde_json::json!({"idempotency_key": Uuid::new_v4().to_string()}),
+    )
+    .await;
+
+    let resp = app.post_draft_action(&draft_id, "", &draft_body()).await;
+
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    let resp = app
+        .post_draft_action(&draft_id, "delete", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/newsletters/drafts");
+
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("<p><i>The draft has been deleted.</i></p>"));
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+}
@@ -2,13 +2,17 @@ use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher};
 use once_cell::sync::Lazy;
 use reqwest::Body;
+use secrecy::ExposeSecret;
 use sha3::Digest;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::net::TcpListener;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_config, DBSettings};
-use zero2prod::startup::Application;
+use wiremock::{MockServer, ResponseTemplate};
+use zero2prod::authentication::create_api_token;
+use zero2prod::configuration::{get_config, DBSettings, EmailTransportKind};
+use zero2prod::email_client::EmailClient;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::startup::{Application, HmacSecret};
 use zero2prod::telemetry::{get_subsciber, init_subscriber};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -27,7 +31,49 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub port: u16,
     pub test_user: TestUser,
+    /// A bearer token for the API, owned by `test_user`.
+    pub api_token: String,
     pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: HmacSecret,
+    pub webhook_secret: String,
+}
+
+/// Answers Postmark batch requests, accepting every email except those
+/// addressed to one of the `rejected` recipients.
+pub struct PostmarkBatchResponder {
+    rejected: Vec<String>,
+}
+
+impl PostmarkBatchResponder {
+    pub fn accepting_all() -> Self {
+        Self { rejected: vec![] }
+    }
+
+    pub fn rejecting(recipients: &[&str]) -> Self {
+        Self {
+            rejected: recipients.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+}
+
+impl wiremock::Respond for PostmarkBatchResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = emails
+            .iter()
+            .map(|email| {
+                if self.rejected.iter().any(|r| email["To"] == r.as_str()) {
+                    serde_json::json!({"ErrorCode": 406, "Message": "Inactive recipient", "To": email["To"]})
+                } else {
+                    serde_json::json!({"ErrorCode": 0, "Message": "OK", "To": email["To"]})
+                }
+            })
+            .collect();
+
+        ResponseTemplate::new(200).set_body_json(results)
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -50,7 +96,7 @@ impl TestUser {
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
 
         let password_hash = Argon2::new(
@@ -63,7 +109,7 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, $2, $3, 'owner')",
             self.user_id,
             self.username,
             password_hash
@@ -73,10 +119,76 @@ impl TestUser {
         .expect("Unable to store test user");
     }
 
-    pub async fn login(&self) {}
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+        .await;
+    }
 }
 
 impl TestApp {
+    /// The test user is an owner unless changed here.
+    pub async fn set_test_user_role(&self, role: &str) {
+        sqlx::query!(
+            "UPDATE users SET role = $1 WHERE user_id = $2",
+            role,
+            self.test_user.user_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+    }
+
+    /// Store a subscriber confirmed on the default list, bypassing the opt-in flow.
+    pub async fn insert_confirmed_subscriber(&self, email: &str) -> Uuid {
+        let subscriber_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at) VALUES ($1, $2, 'le guin', now(), 'confirmed', now())"#,
+            subscriber_id,
+            email
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at) SELECT list_id, $1, 'confirmed', now() FROM lists WHERE slug = 'newsletter'"#,
+            subscriber_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+        subscriber_id
+    }
+
+    /// Target an issue stored directly in the database at the default list.
+    pub async fn target_default_list(&self, issue_id: Uuid) {
+        sqlx::query!(
+            r#"INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id) SELECT $1, list_id FROM lists WHERE slug = 'newsletter'"#,
+            issue_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/subscriptions", &self.address))
@@ -87,11 +199,36 @@ impl TestApp {
             .expect("Unable to execute request")
     }
 
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/subscriptions/resend_confirmation",
+                &self.address
+            ))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Unable to execute request")
+    }
+
+    /// Publish through the API with the test user's token and a fresh idempotency key.
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_api_newsletters(&self.api_token, &Uuid::new_v4().to_string(), &body)
+            .await
+    }
+
+    pub async fn post_api_newsletters(
+        &self,
+        token: &str,
+        idempotency_key: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
-            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
-            .json(&body)
+            .post(&format!("{}/api/v1/newsletters", &self.address))
+            .bearer_auth(token)
+            .header("Idempotency-Key", idempotency_key)
+            .json(body)
             .send()
             .await
             .expect("Unable to execute request")
@@ -122,6 +259,27 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    /// Extract the unsubscribe link of the first email in a batch request.
+    pub fn get_unsubscribe_link(&self, request: &wiremock::Request) -> reqwest::Url {
+        let body = serde_json::from_slice::<serde_json::Value>(&request.body).unwrap();
+        let header = body[0]["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .expect("No List-Unsubscribe header was set.");
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+
+        let mut unsubscribe_link = reqwest::Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -189,6 +347,325 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_newsletter_issue_status(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn post_cancel_newsletter_issue(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_draft(&self, draft_id: &uuid::Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    /// POST to one of the actions of a draft, e.g. `test` or `publish`.
+    /// An empty `action` updates the draft itself.
+    pub async fn post_draft_action<Body>(
+        &self,
+        draft_id: &uuid::Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut url = format!("{}/admin/newsletters/drafts/{}", &self.address, draft_id);
+        if !action.is_empty() {
+            url = format!("{}/{}", url, action);
+        }
+        self.api_client
+            .post(url)
+            .form(body)
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_draft_preview(
+        &self,
+        draft_id: &uuid::Uuid,
+        format: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}/preview?format={}",
+                &self.address, draft_id, format
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletter", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter().await.text().await.unwrap()
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/tokens", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/tokens", &self.address))
+            .form(&serde_json::json!({ "name": name }))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn post_revoke_api_token(&self, token_id: &uuid::Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/tokens/{}/revoke",
+                &self.address, token_id
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: &uuid::Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: &uuid::Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn post_subscriber_tags(
+        &self,
+        subscriber_id: &uuid::Uuid,
+        tags: &str,
+        attributes: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .form(&serde_json::json!({ "tags": tags, "attributes": attributes }))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    /// Upload a CSV file to the import form as `multipart/form-data`.
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let boundary = "zero2prod-test-boundary";
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            {mode}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_export_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list(&self, name: &str, slug: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(&serde_json::json!({ "name": name, "slug": slug }))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    /// `path` is relative to `/archive`, e.g. `/feed.xml`.
+    pub async fn get_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_invite_user(&self, email: &str, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/invite", &self.address))
+            .form(&serde_json::json!({ "email": email, "role": role }))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    /// `action` is one of `disable`, `enable` or `delete`.
+    pub async fn post_user_action(&self, user_id: &uuid::Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, user_id, action
+            ))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_security_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/security", &self.address))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `path` is one of `/admin/security/totp`, `/admin/security/totp/confirm`,
+    /// `/admin/security/totp/disable` or `/login/two_factor`.
+    pub async fn post_totp_code(&self, path: &str, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}{}", &self.address, path))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn get_archive(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/archive{}", &self.address, path))
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
+    pub async fn post_postmark_webhook(
+        &self,
+        secret: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth("postmark", Some(secret))
+            .json(body)
+            .send()
+            .await
+            .expect("Unable to execute request.")
+    }
+
     pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -233,6 +710,7 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        c.email_client.transport = EmailTransportKind::Postmark;
         c
     };
 
@@ -255,18 +733,33 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
-    let test_app = TestApp {
+    let test_user = TestUser::generate();
+    test_user.store(&db_pool).await;
+    let api_token = create_api_token(&db_pool, test_user.user_id, "test")
+        .await
+        .unwrap()
+        .expose_secret()
+        .to_owned();
+
+    let webhook_secret = config
+        .email_client
+        .webhook_secret
+        .expose_secret()
+        .to_owned();
+
+    TestApp {
         address,
         db_pool,
         email_server,
         port,
         api_client,
-        test_user: TestUser::generate(),
-    };
-
-    test_app.test_user.store(&test_app.db_pool).await;
-
-    test_app
+        test_user,
+        api_token,
+        email_client: config.email_client.client(),
+        base_url: config.application.base_url,
+        hmac_secret: HmacSecret(config.application.hmac_secret),
+        webhook_secret,
+    }
 }
 
 pub fn assert_is_redirect_to(resp: &reqwest::Response, location: &str) {
@@ -0,0 +1,303 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::MAX_DELIVERY_ATTEMPTS;
+
+const SUBSCRIBER_EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn enqueue_issue(app: &TestApp, n_retries: i16) -> Uuid {
+    enqueue_issue_for(app, &[SUBSCRIBER_EMAIL], n_retries).await
+}
+
+async fn enqueue_issue_for(app: &TestApp, emails: &[&str], n_retries: i16) -> Uuid {
+    for email in emails {
+        app.insert_confirmed_subscriber(email).await;
+    }
+
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at) VALUES ($1, 'Newsletter title', 'Newsletter body as plain text', '<p>Newsletter body as HTML</p>', now())"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.target_default_list(issue_id).await;
+
+    for email in emails {
+        sqlx::query!(
+            r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, n_retries) VALUES ($1, $2, $3)"#,
+            issue_id,
+            email,
+            n_retries
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    issue_id
+}
+
+async fn make_queue_due(app: &TestApp) {
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn successful_delivery_removes_the_task_from_the_queue() {
+    let app = spawn_app().await;
+    enqueue_issue(&app, 0).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 0);
+}
+
+#[tokio::test]
+async fn failed_delivery_is_rescheduled_with_backoff() {
+    let app = spawn_app().await;
+    enqueue_issue(&app, 0).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() as "in_the_future!" FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        SUBSCRIBER_EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The failed task was dropped from the queue.");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.in_the_future);
+}
+
+#[tokio::test]
+async fn rescheduled_task_is_delivered_once_it_is_due() {
+    let app = spawn_app().await;
+    enqueue_issue(&app, 0).await;
+
+    let mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(mock_guard);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    make_queue_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    let n_dead_letters =
+        sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_dead_letters"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_tasks, 0);
+    assert_eq!(n_dead_letters, 0);
+}
+
+#[tokio::test]
+async fn task_exhausting_its_retries_is_moved_to_dead_letters() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_issue(&app, MAX_DELIVERY_ATTEMPTS - 1).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 0);
+
+    let dead_letter = sqlx::query!(
+        "SELECT newsletter_issue_id, subscriber_email, n_attempts, last_error FROM issue_delivery_dead_letters"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The failed task was not moved to the dead letters.");
+    assert_eq!(dead_letter.newsletter_issue_id, issue_id);
+    assert_eq!(dead_letter.subscriber_email, SUBSCRIBER_EMAIL);
+    assert_eq!(dead_letter.n_attempts, MAX_DELIVERY_ATTEMPTS);
+    assert!(dead_letter.last_error.contains("500"));
+
+    let outcome = sqlx::query!("SELECT outcome FROM newsletter_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "failed");
+}
+
+#[tokio::test]
+async fn tasks_that_are_not_due_are_not_picked_up() {
+    let app = spawn_app().await;
+    enqueue_issue(&app, 0).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now() + interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn delivery_attempts_are_recorded_in_the_delivery_log() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_issue(&app, 0).await;
+
+    let mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(mock_guard);
+
+    let delivery = sqlx::query!(
+        "SELECT outcome, n_attempts FROM newsletter_deliveries WHERE newsletter_issue_id = $1 AND subscriber_email = $2",
+        issue_id,
+        SUBSCRIBER_EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The delivery attempt was not recorded.");
+    assert_eq!(delivery.outcome, "retrying");
+    assert_eq!(delivery.n_attempts, 1);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    make_queue_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!(
+        "SELECT outcome, provider_response, n_attempts FROM newsletter_deliveries WHERE newsletter_issue_id = $1 AND subscriber_email = $2",
+        issue_id,
+        SUBSCRIBER_EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.outcome, "sent");
+    assert!(delivery
+        .provider_response
+        .unwrap()
+        .contains(r#""ErrorCode":0"#));
+    assert_eq!(delivery.n_attempts, 2);
+}
+
+#[tokio::test]
+async fn due_tasks_are_sent_in_a_single_batch() {
+    let app = spawn_app().await;
+    let emails = ["a@example.com", "b@example.com", "c@example.com"];
+    enqueue_issue_for(&app, &emails, 0).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), emails.len());
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 0);
+}
+
+#[tokio::test]
+async fn rejected_emails_in_a_batch_are_retried_while_accepted_ones_are_removed() {
+    let app = spawn_app().await;
+    enqueue_issue_for(&app, &["accepted@example.com", "rejected@example.com"], 0).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::rejecting(&["rejected@example.com"]))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let tasks = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].subscriber_email, "rejected@example.com");
+    assert_eq!(tasks[0].n_retries, 1);
+
+    let rejected = sqlx::query!(
+        "SELECT outcome, provider_response FROM newsletter_deliveries WHERE subscriber_email = 'rejected@example.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(rejected.outcome, "retrying");
+    assert!(rejected
+        .provider_response
+        .unwrap()
+        .contains("Inactive recipient"));
+}
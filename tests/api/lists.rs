@@ -0,0 +1,229 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(app: &TestApp, slug: &str) {
+    app.test_user.login(app).await;
+    let response = app.post_create_list("Release notes", slug).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+}
+
+/// Subscribe to a list and follow the confirmation link.
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}&list={}",
+        urlencoding::encode(email),
+        list
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn list_status(app: &TestApp, email: &str, slug: &str) -> Option<String> {
+    sqlx::query!(
+        r#"
+        SELECT ls.status FROM list_subscriptions ls
+        JOIN lists l USING (list_id)
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        slug
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app.post_create_list("Release notes", "releases").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_create_lists() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+
+    let html = app.get_lists_html().await;
+    assert!(html.contains("<p><i>The list `releases` has been created.</i></p>"));
+    assert!(html.contains("<td>Release notes</td><td><code>releases</code></td>"));
+    assert!(html.contains("<code>newsletter</code>"));
+
+    app.post_create_list("Again", "releases").await;
+    let html = app.get_lists_html().await;
+    assert!(html.contains("<p><i>There is already a list named `releases`.</i></p>"));
+
+    app.post_create_list("Bad", "Not A Slug").await;
+    let html = app.get_lists_html().await;
+    assert!(html.contains("lowercase letters, digits or dashes"));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com&list=nope".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirmation_only_applies_to_the_list_that_was_joined() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+
+    subscribe_and_confirm(&app, "ursula@example.com", "releases").await;
+
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "releases").await,
+        Some("confirmed".into())
+    );
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "newsletter").await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn joining_another_list_requires_its_own_confirmation() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com&list=releases".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "releases").await,
+        Some("pending_confirmation".into())
+    );
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "newsletter").await,
+        Some("confirmed".into())
+    );
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_the_targeted_lists() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "terry@example.com", "releases").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Release 1.0",
+            "content": {
+                "text": "Release notes",
+                "html": "<p>Release notes</p>",
+            },
+            "lists": ["releases"]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let batch = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|r| r.url.path() == "/email/batch")
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&batch.body).unwrap();
+    let recipients: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["To"].as_str().unwrap())
+        .collect();
+    assert_eq!(recipients, vec!["terry@example.com"]);
+}
+
+#[tokio::test]
+async fn subscribers_on_several_targeted_lists_get_a_single_email() {
+    let app = spawn_app().await;
+    create_list(&app, "releases").await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "ursula@example.com", "releases").await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "lists": "newsletter, releases",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 1);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "lists": ["nope"]
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -1,9 +1,29 @@
+mod api_tokens;
+mod archive;
+mod bootstrap;
 mod dashboard;
+mod drafts;
 mod health_check;
 mod helper;
+mod issue_delivery_worker;
+mod lists;
+mod markdown;
+mod merge_fields;
 mod newsletter;
+mod newsletter_status;
+mod password_reset;
+mod roles;
+mod scheduled_issues;
+mod segments;
+mod subscriber_csv;
+mod subscribers;
 mod subscription_confirm;
 mod subscriptions;
+mod tracking;
+mod two_factor;
+mod unsubscribe;
+mod users;
+mod webhooks;
 
 mod change_password;
 mod login;
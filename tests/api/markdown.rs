@@ -0,0 +1,131 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+struct StoredContent {
+    html_content: String,
+    text_content: String,
+    markdown_content: Option<String>,
+}
+
+async fn stored_content(app: &TestApp) -> StoredContent {
+    sqlx::query_as!(
+        StoredContent,
+        "SELECT html_content, text_content, markdown_content FROM newsletter_issues"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn markdown_issues_are_sent_as_html_and_plain_text() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "# News\n\nHi {{ name }}, read [the post](https://example.com/post).",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let batch = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&batch.body).unwrap();
+    assert!(body[0]["HtmlBody"].as_str().unwrap().ends_with(
+        "\n<h1>News</h1>\n<p>Hi le guin, read <a href=\"https://example.com/post\" rel=\"noopener noreferrer\">the post</a>.</p>\n"
+    ));
+    assert!(body[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("\n\nNews\n====\n\nHi le guin, read the post (https://example.com/post)."));
+}
+
+#[tokio::test]
+async fn the_markdown_source_is_stored_with_the_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Some **bold** news <script>alert(1)</script>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let content = stored_content(&app).await;
+    assert_eq!(
+        content.markdown_content.as_deref(),
+        Some("Some **bold** news <script>alert(1)</script>")
+    );
+    assert_eq!(
+        content.html_content,
+        "<p>Some <strong>bold</strong> news </p>\n"
+    );
+    assert_eq!(content.text_content, "Some bold news");
+}
+
+#[tokio::test]
+async fn the_two_field_mode_is_still_accepted() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let content = stored_content(&app).await;
+    assert_eq!(content.markdown_content, None);
+    assert_eq!(content.html_content, "<p>Newsletter body as HTML</p>");
+    assert_eq!(content.text_content, "Newsletter body as plain text");
+}
+
+#[tokio::test]
+async fn markdown_drafts_can_be_edited_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Draft with *emphasis*",
+        }))
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap();
+    let draft_id: Uuid = location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains(
+        r#"<textarea name="markdown_content" rows="20" cols="50">Draft with *emphasis*</textarea>"#
+    ));
+    let preview = app
+        .get_draft_preview(&draft_id, "html")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(preview, "<p>Draft with <em>emphasis</em></p>\n");
+}
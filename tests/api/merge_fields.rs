@@ -0,0 +1,195 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str) -> Uuid {
+    let subscriber_id = app.insert_confirmed_subscriber(email).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET name = $2, confirmed_at = '2022-07-01T09:00:00Z' WHERE id = $1",
+        subscriber_id,
+        name
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn merge_fields_are_rendered_for_every_recipient() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula").await;
+    insert_subscriber(&app, "terry@example.com", "Terry <Pratchett>").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi {{ name }}, subscribed since {{confirm_date}}. Leave: {{ unsubscribe_url }}",
+                "html": "<p>Hi {{ name }} ({{ email }})</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let batch = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&batch.body).unwrap();
+    let email_for = |recipient: &str| {
+        body.as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["To"] == recipient)
+            .unwrap()
+            .clone()
+    };
+    let ursula = email_for("ursula@example.com");
+    assert!(ursula["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("\n<p>Hi Ursula (ursula@example.com)</p>"));
+    let text = ursula["TextBody"].as_str().unwrap();
+    assert!(text.contains("\n\nHi Ursula, subscribed since 2022-07-01. Leave: http"));
+    let unsubscribe_link = text.rsplit(' ').next().unwrap();
+    assert!(ursula["Headers"].to_string().contains(unsubscribe_link));
+
+    let terry = email_for("terry@example.com");
+    assert!(terry["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("\n<p>Hi Terry &lt;Pratchett&gt; (terry@example.com)</p>"));
+    assert!(terry["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("\n\nHi Terry <Pratchett>,"));
+}
+
+#[tokio::test]
+async fn unknown_merge_fields_are_rejected_by_the_api() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi {{ nmae }}",
+                "html": "<p>Hi {{ name }}</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("nmae"));
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn unclosed_merge_fields_are_rejected_by_the_admin_form() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi",
+            "html_content": "<p>Hi {{ name</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_draft_with_an_invalid_merge_field_stays_a_draft() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ first_name }}",
+            "html_content": "<p>Hi</p>",
+        }))
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap();
+    let draft_id: Uuid = location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let response = app
+        .post_draft_action(
+            &draft_id,
+            "publish",
+            &serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let status = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status;
+    assert_eq!(status, "draft");
+}
+
+#[tokio::test]
+async fn test_emails_render_merge_fields_with_sample_values() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ name }}",
+            "html_content": "<p>Hi {{ email }}</p>",
+        }))
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let draft_id: Uuid = location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_draft_action(&draft_id, "test", &serde_json::json!({}))
+        .await;
+
+    let email = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email.body).unwrap();
+    assert_eq!(body["TextBody"], "Hi Test Subscriber");
+    assert_eq!(body["HtmlBody"], "<p>Hi admin@example.com</p>");
+}
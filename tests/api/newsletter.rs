@@ -2,13 +2,15 @@ use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-use crate::helper::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helper::{
+    assert_is_redirect_to, spawn_app, ConfirmationLinks, PostmarkBatchResponder, TestApp,
+};
 
 #[tokio::test]
 async fn newsletter_are_not_delivered_to_unconfirmed_subscribers() {
     let app = spawn_app().await;
 
-    create_unconfirmed_subscriber(&app);
+    create_unconfirmed_subscriber(&app).await;
 
     Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
@@ -26,7 +28,8 @@ async fn newsletter_are_not_delivered_to_unconfirmed_subscribers() {
 
     let response = app.post_newsletters(newsletter_req_body).await;
 
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -35,8 +38,9 @@ async fn newsletter_are_delivered_to_confirmed_subscribers() {
 
     create_confirmed_subscriber(&app).await;
 
-    Mock::given(any())
-        .respond_with(ResponseTemplate::new(200))
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -50,7 +54,8 @@ async fn newsletter_are_delivered_to_confirmed_subscribers() {
     });
     let response = app.post_newsletters(newsletter_req_body).await;
 
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -87,81 +92,146 @@ async fn newsletter_returns_400_for_invalid_data() {
     }
 }
 
+fn api_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
 #[tokio::test]
-async fn non_existing_user_is_rejected() {
+async fn unknown_tokens_are_rejected() {
     let app = spawn_app().await;
 
-    let username = Uuid::new_v4().to_string();
-    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_api_newsletters(
+            &Uuid::new_v4().to_string(),
+            &Uuid::new_v4().to_string(),
+            &api_request_body(),
+        )
+        .await;
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Bearer realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn basic_credentials_are_rejected() {
+    let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(format!("{}/newsletters", app.address))
-        .basic_auth(username, Some(password))
-        .json(&serde_json::json!({
-            "title": "Newsletter title",
-            "content": {
-                "text": "Newsletter body as plain text",
-                "html": "<p>Newsletter body as HTML</p>",
-            }
-        }))
+        .post(format!("{}/api/v1/newsletters", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&api_request_body())
         .send()
         .await
         .expect("Unable to execute request.");
 
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", app.address))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&api_request_body())
+        .send()
+        .await
+        .expect("Unable to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
     assert_eq!(
-        r#"Basic realm="publish""#,
+        r#"Bearer realm="publish""#,
         response.headers()["WWW-Authenticate"]
     );
-    assert_eq!(401, response.status().as_u16());
 }
 
 #[tokio::test]
-async fn invalid_password_is_rejected() {
+async fn requests_missing_an_idempotency_key_are_rejected() {
     let app = spawn_app().await;
-    let username = &app.test_user.username;
-    let password = Uuid::new_v4().to_string();
 
-    assert_ne!(password, app.test_user.password);
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", app.address))
+        .bearer_auth(&app.api_token)
+        .json(&api_request_body())
+        .send()
+        .await
+        .expect("Unable to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_basic_auth_endpoint_is_gone() {
+    let app = spawn_app().await;
 
     let response = reqwest::Client::new()
         .post(format!("{}/newsletters", app.address))
-        .basic_auth(username, Some(password))
-        .json(&serde_json::json!({
-            "title": "Newsletter title",
-            "content": {
-                "text": "Newsletter body as plain text",
-                "html": "<p>Newsletter body as HTML</p>",
-            }
-        }))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&api_request_body())
         .send()
         .await
         .expect("Unable to execute request.");
 
-    assert_eq!(401, response.status().as_u16());
-    assert_eq!(
-        r#"Basic realm="publish""#,
-        response.headers()["WWW-Authenticate"]
-    );
+    assert_eq!(response.status().as_u16(), 404);
 }
 
 #[tokio::test]
-async fn requests_missing_authorization_are_rejected() {
+async fn api_publishing_is_idempotent() {
     let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
 
-    let body = serde_json::json!({
-        "title": "Newsletter title",
-        "content": {
-            "text": "Newsletter body as plain text",
-            "html": "<p>Newsletter body as HTML</p>",
-        }
-    });
-    let response = app.post_newsletters(body).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
 
-    assert_eq!(response.status().as_u16(), 401);
-    assert_eq!(
-        r#"Basic realm="publish""#,
-        response.headers()["WWW-Authenticate"]
-    );
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response1 = app
+        .post_api_newsletters(&app.api_token, &idempotency_key, &api_request_body())
+        .await;
+    let response2 = app
+        .post_api_newsletters(&app.api_token, &idempotency_key, &api_request_body())
+        .await;
+
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    let body1: serde_json::Value = response1.json().await.unwrap();
+    let body2: serde_json::Value = response2.json().await.unwrap();
+    assert_eq!(body1, body2);
+    assert!(body1["newsletter_issue_id"].is_string());
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn api_requests_record_when_the_token_was_last_used() {
+    let app = spawn_app().await;
+
+    app.post_newsletters(api_request_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let last_used_at = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .last_used_at;
+    assert!(last_used_at.is_some());
 }
 
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
@@ -199,59 +269,156 @@ async fn create_confirmed_subscriber(app: &TestApp) {
         .unwrap();
 }
 
-// async fn newsletter_creation_is_idempotent() {
-//     let app = spawn_app().await;
-//     create_confirmed_subscriber(&app).await;
-
-//     app.test_user.login().await;
-
-//     Mock::given(path("/email"))
-//         .and(method("POST"))
-//         .respond_with(ResponseTemplate::new(200))
-//         .expect(1)
-//         .mount(&app.email_server)
-//         .await;
-
-//     let newletter_request_body = serde_json::json!({
-//         "title": "Newsletter title",
-//         "text_content": "Newletter body as plain text",
-//         "html_content": "<p>Newsletter body as HTML</p>",
-//         "idempotency_key": uuid::Uuid::new_v4().to_string()
-//     });
-
-//     let resp = app.post_publish_newsletter(&newletter_request_body).await;
-//     assert_is_redirect_to(&resp, "/admin/newsletter");
-// }
-
-// #[tokio::test]
-// async fn concurrent_form_submission_is_handled_gracefully() {
-//     // Arrange
-//     let app = spawn_app().await;
-//     create_confirmed_subscriber(&app).await;
-//     app.test_user.login(&app).await;
-
-//     Mock::given(path("/email"))
-//         .and(method("POST"))
-//         // Setting a long delay to ensure that the second request
-//         // arrives before the first one completes
-//         .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
-//         .expect(1)
-//         .mount(&app.email_server)
-//         .await;
-
-//     // Act - Submit two newsletter forms concurrently
-//     let newsletter_request_body = serde_json::json!({
-//         "title": "Newsletter title",
-//         "text_content": "Newsletter body as plain text",
-//         "html_content": "<p>Newsletter body as HTML</p>",
-//         "idempotency_key": uuid::Uuid::new_v4().to_string()
-//     });
-//     let response1 = app.post_publish_newsletter(&newsletter_request_body);
-//     let response2 = app.post_publish_newsletter(&newsletter_request_body);
-//     let (response1, response2) = tokio::join!(response1, response2);
-
-//     assert_eq!(response1.status(), response2.status());
-//     assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
-
-//     // Mock verifies on Drop that we have sent the newsletter email **once**
-// }
+/// Store a confirmed subscriber directly, bypassing the confirmation emails.
+async fn insert_confirmed_subscriber(app: &TestApp) {
+    app.insert_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+}
+
+fn newsletter_form_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_the_newsletter_form() {
+    let app = spawn_app().await;
+
+    let resp = app.get_publish_newsletter().await;
+
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_publish_a_newsletter() {
+    let app = spawn_app().await;
+
+    let resp = app.post_publish_newsletter(&newsletter_form_body()).await;
+
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn newsletter_form_carries_a_fresh_idempotency_key() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let first_form = app.get_publish_newsletter_html().await;
+    let second_form = app.get_publish_newsletter_html().await;
+
+    assert!(first_form.contains(r#"<form action="/admin/newsletter" method="post">"#));
+    assert!(first_form.contains(r#"name="idempotency_key""#));
+    assert_ne!(first_form, second_form);
+}
+
+#[tokio::test]
+async fn the_dashboard_links_to_the_newsletter_form() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains(r#"<a href="/admin/newsletter">"#));
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = newsletter_form_body();
+    let resp = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&resp, "/admin/newsletter");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issus has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    // Submit the same form again
+    let resp = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&resp, "/admin/newsletter");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issus has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_form_submission_is_handled_gracefully() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Submit two newsletter forms concurrently
+    let newsletter_request_body = newsletter_form_body();
+    let response1 = app.post_publish_newsletter(&newsletter_request_body);
+    let response2 = app.post_publish_newsletter(&newsletter_request_body);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn newsletter_can_be_scheduled_from_the_form() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_form_body();
+    body["publish_at"] = "2099-01-01T09:00:00Z".into();
+    let resp = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&resp, "/admin/newsletter");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled"));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletter_form_rejects_an_invalid_publish_at() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let mut body = newsletter_form_body();
+    body["publish_at"] = "tomorrow".into();
+    let resp = app.post_publish_newsletter(&body).await;
+
+    assert_eq!(resp.status().as_u16(), 400);
+}
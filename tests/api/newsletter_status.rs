@@ -0,0 +1,80 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn create_issue(app: &TestApp) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at) VALUES ($1, 'Newsletter title', 'Newsletter body as plain text', '<p>Newsletter body as HTML</p>', now())"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    issue_id
+}
+
+async fn record_delivery(app: &TestApp, issue_id: Uuid, email: &str, outcome: &str) {
+    sqlx::query!(
+        r#"INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, outcome, n_attempts, first_attempted_at, last_attempted_at) VALUES ($1, $2, $3, 1, now(), now())"#,
+        issue_id,
+        email,
+        outcome
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_the_delivery_status() {
+    let app = spawn_app().await;
+    let issue_id = create_issue(&app).await;
+
+    let resp = app.get_newsletter_issue_status(&issue_id).await;
+
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn unknown_issue_returns_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let resp = app.get_newsletter_issue_status(&Uuid::new_v4()).await;
+
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn delivery_status_counts_sent_failed_and_pending_recipients() {
+    let app = spawn_app().await;
+    let issue_id = create_issue(&app).await;
+    record_delivery(&app, issue_id, "sent-1@example.com", "sent").await;
+    record_delivery(&app, issue_id, "sent-2@example.com", "sent").await;
+    record_delivery(&app, issue_id, "failed@example.com", "failed").await;
+    record_delivery(&app, issue_id, "retrying@example.com", "retrying").await;
+    for email in ["retrying@example.com", "pending@example.com"] {
+        sqlx::query!(
+            r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) VALUES ($1, $2)"#,
+            issue_id,
+            email
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    app.test_user.login(&app).await;
+
+    let html_page = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("<h1>Newsletter title</h1>"));
+    assert!(html_page.contains("<li>Sent: 2</li>"));
+    assert!(html_page.contains("<li>Failed: 1</li>"));
+    assert!(html_page.contains("<li>Pending: 2</li>"));
+}
@@ -0,0 +1,144 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn set_test_user_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'le_guin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn post_forgot_password(app: &TestApp, username: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/password_reset", &app.address))
+        .form(&serde_json::json!({ "username": username }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn post_reset_password(
+    app: &TestApp,
+    link: &reqwest::Url,
+    password: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(link.clone())
+        .form(&serde_json::json!({
+            "new_password": password,
+            "new_password_check": password,
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The email is sent in the background, after the response.
+async fn reset_link(app: &TestApp) -> reqwest::Url {
+    for _ in 0..50 {
+        let requests = app.email_server.received_requests().await.unwrap();
+        if let Some(request) = requests.last() {
+            return app.get_confirmation_link(request).html;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("No password reset email was sent.");
+}
+
+#[tokio::test]
+async fn a_password_can_be_reset_by_email_once() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = post_forgot_password(&app, &app.test_user.username).await;
+    assert_is_redirect_to(&resp, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("a reset link has been sent to it.</i></p>"));
+
+    let link = reset_link(&app).await;
+    let resp = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let resp = post_reset_password(&app, &link, "a-new-password").await;
+    assert_is_redirect_to(&resp, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your password has been reset, you can now log in.</i></p>"));
+
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+
+    // The link cannot be used twice.
+    let resp = post_reset_password(&app, &link, "yet-another-password").await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_response_is_the_same_for_unknown_usernames() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // The test user exists, but has no email address.
+    for username in [app.test_user.username.as_str(), "nobody"] {
+        let resp = post_forgot_password(&app, username).await;
+        assert_is_redirect_to(&resp, "/login");
+        let html_page = app.get_login_html().await;
+        assert!(html_page.contains("a reset link has been sent to it.</i></p>"));
+    }
+}
+
+#[tokio::test]
+async fn resetting_a_password_ends_all_sessions() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+
+    post_forgot_password(&app, &app.test_user.username).await;
+    let link = reset_link(&app).await;
+    let resp = post_reset_password(&app, &link, "a-new-password").await;
+    assert_is_redirect_to(&resp, "/login");
+
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn reset_links_must_be_valid() {
+    let app = spawn_app().await;
+
+    let resp = app
+        .api_client
+        .get(format!(
+            "{}/password_reset/confirm?token=not-a-token",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 401);
+}
@@ -0,0 +1,136 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+fn newsletter_form_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn viewers_can_see_the_admin_area() {
+    let app = spawn_app().await;
+    app.set_test_user_role("viewer").await;
+    app.test_user.login(&app).await;
+
+    let resp = app.get_admin_dashboard().await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let resp = app.get_subscribers("").await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_an_issue() {
+    let app = spawn_app().await;
+    app.set_test_user_role("viewer").await;
+    app.test_user.login(&app).await;
+
+    let resp = app.get_publish_newsletter().await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let resp = app.post_publish_newsletter(&newsletter_form_body()).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert!(resp
+        .text()
+        .await
+        .unwrap()
+        .contains("You need to be an editor to do this."));
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn viewers_cannot_create_drafts_or_lists() {
+    let app = spawn_app().await;
+    app.set_test_user_role("viewer").await;
+    app.test_user.login(&app).await;
+
+    let resp = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let resp = app.post_create_list("Product updates", "updates").await;
+    assert_eq!(resp.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_cannot_create_api_tokens() {
+    let app = spawn_app().await;
+    app.set_test_user_role("viewer").await;
+    app.test_user.login(&app).await;
+
+    let resp = app.post_create_api_token("ci").await;
+
+    assert_eq!(resp.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_through_the_api() {
+    let app = spawn_app().await;
+    app.set_test_user_role("viewer").await;
+
+    let resp = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(resp.status().as_u16(), 403);
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn editors_can_publish_an_issue() {
+    let app = spawn_app().await;
+    app.set_test_user_role("editor").await;
+    app.test_user.login(&app).await;
+
+    let resp = app.post_publish_newsletter(&newsletter_form_body()).await;
+
+    assert_is_redirect_to(&resp, "/admin/newsletter");
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn the_session_of_a_deleted_user_is_ended() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    sqlx::query!(
+        "DELETE FROM api_tokens WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "DELETE FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+}
@@ -0,0 +1,142 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Enqueue an issue for a confirmed subscriber, due in `delay` from now.
+async fn enqueue_scheduled_issue(app: &TestApp, delay: chrono::Duration) -> Uuid {
+    app.insert_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at, publish_at) VALUES ($1, 'Newsletter title', 'Newsletter body as plain text', '<p>Newsletter body as HTML</p>', now(), $2)"#,
+        issue_id,
+        chrono::Utc::now() + delay
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.target_default_list(issue_id).await;
+    sqlx::query!(
+        r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) SELECT $1, email FROM subscriptions WHERE status = 'confirmed'"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    issue_id
+}
+
+async fn count_pending_tasks(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_they_are_due() {
+    let app = spawn_app().await;
+    enqueue_scheduled_issue(&app, chrono::Duration::hours(1)).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(count_pending_tasks(&app).await, 1);
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_they_are_due() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_scheduled_issue(&app, chrono::Duration::hours(1)).await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET publish_at = now() WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(count_pending_tasks(&app).await, 0);
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_cancel_an_issue() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_scheduled_issue(&app, chrono::Duration::hours(1)).await;
+
+    let resp = app.post_cancel_newsletter_issue(&issue_id).await;
+
+    assert_is_redirect_to(&resp, "/login");
+    assert_eq!(count_pending_tasks(&app).await, 1);
+}
+
+#[tokio::test]
+async fn cancelling_a_scheduled_issue_drops_its_pending_deliveries() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_scheduled_issue(&app, chrono::Duration::hours(1)).await;
+    app.test_user.login(&app).await;
+
+    let html_page = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="/admin/newsletters/{}/cancel" method="post">"#,
+        issue_id
+    )));
+
+    let resp = app.post_cancel_newsletter_issue(&issue_id).await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/{}", issue_id));
+
+    let html_page = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("<p>Cancelled at "));
+    assert_eq!(count_pending_tasks(&app).await, 0);
+}
+
+#[tokio::test]
+async fn issues_that_are_already_going_out_cannot_be_cancelled() {
+    let app = spawn_app().await;
+    let issue_id = enqueue_scheduled_issue(&app, chrono::Duration::zero()).await;
+    app.test_user.login(&app).await;
+
+    let resp = app.post_cancel_newsletter_issue(&issue_id).await;
+    assert_is_redirect_to(&resp, &format!("/admin/newsletters/{}", issue_id));
+
+    let html_page = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "<p><i>Only scheduled issues that have not started going out can be cancelled.</i></p>"
+    ));
+    assert_eq!(count_pending_tasks(&app).await, 1);
+}
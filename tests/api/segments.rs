@@ -0,0 +1,168 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn tag(app: &TestApp, subscriber_id: Uuid, tags: &str, attributes: &str) {
+    let response = app
+        .post_subscriber_tags(&subscriber_id, tags, attributes)
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+}
+
+async fn queued_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue ORDER BY subscriber_email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect()
+}
+
+async fn publish_with_segment(app: &TestApp, segment: &str) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "segment": segment
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    tag(
+        &app,
+        subscriber_id,
+        "VIP, beta",
+        "country=FR\ncity=New York",
+    )
+    .await;
+
+    let html = app
+        .get_subscriber_details(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p><i>The tags and attributes have been saved.</i></p>"));
+    assert!(html.contains(r#"name="tags" value="beta, vip""#));
+    assert!(html.contains("city=New York\ncountry=FR\n</textarea>"));
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = app.insert_confirmed_subscriber("ursula@example.com").await;
+    tag(&app, subscriber_id, "vip", "").await;
+
+    tag(&app, subscriber_id, "not a tag", "").await;
+
+    let html = app
+        .get_subscriber_details(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("is not a valid tag"));
+    assert!(html.contains(r#"name="tags" value="vip""#));
+}
+
+#[tokio::test]
+async fn issues_are_only_queued_for_subscribers_matching_the_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let vip = app.insert_confirmed_subscriber("vip@example.com").await;
+    let churned_vip = app.insert_confirmed_subscriber("churned@example.com").await;
+    let italian_vip = app.insert_confirmed_subscriber("italian@example.com").await;
+    app.insert_confirmed_subscriber("plain@example.com").await;
+    tag(&app, vip, "vip", "country=FR").await;
+    tag(&app, churned_vip, "vip, churned", "country=FR").await;
+    tag(&app, italian_vip, "vip", "country=IT").await;
+
+    let response = publish_with_segment(&app, "tag:vip -tag:churned attr.country:FR").await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(queued_emails(&app).await, vec!["vip@example.com"]);
+}
+
+#[tokio::test]
+async fn segments_can_filter_on_the_subscription_date() {
+    let app = spawn_app().await;
+    let early = app.insert_confirmed_subscriber("early@example.com").await;
+    app.insert_confirmed_subscriber("late@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = '2022-01-15T10:00:00Z' WHERE id = $1",
+        early
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = publish_with_segment(&app, "subscribed_before:2022-02-01").await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(queued_emails(&app).await, vec!["early@example.com"]);
+}
+
+#[tokio::test]
+async fn an_empty_segment_targets_every_subscriber() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    app.insert_confirmed_subscriber("terry@example.com").await;
+
+    let response = publish_with_segment(&app, "").await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(
+        queued_emails(&app).await,
+        vec!["terry@example.com", "ursula@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    let response = publish_with_segment(&app, "colour:red").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(queued_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_segment_is_stored_with_the_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment": "attr.country:FR   tag:VIP",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id, segment FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.segment.as_deref(), Some("tag:vip attr.country:FR"));
+    let html = app
+        .get_newsletter_issue_status(&issue.newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p>Segment: <code>tag:vip attr.country:FR</code></p>"));
+}
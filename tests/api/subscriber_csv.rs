@@ -0,0 +1,161 @@
+use crate::helper::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_or_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_import_subscribers("email,name\nursula@example.com,Ursula", "confirmed")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_export_subscribers().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn import_validates_every_row_and_reports_errors() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\n\
+        ursula@example.com,Ursula\n\
+        not-an-email,Broken\n\
+        terry@example.com,\n\
+        terry@example.com,Terry";
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>2 imported, 0 skipped, 2 with errors.</p>"));
+    assert!(html.contains("<tr><td>2</td><td>ursula@example.com</td><td>imported</td>"));
+    assert!(html.contains("<tr><td>3</td><td>not-an-email</td><td>error</td>"));
+    assert!(html.contains("<tr><td>4</td><td>terry@example.com</td><td>error</td>"));
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "terry@example.com");
+    assert_eq!(saved[0].status, "confirmed");
+    assert_eq!(saved[1].email, "ursula@example.com");
+    assert_eq!(saved[1].status, "confirmed");
+}
+
+#[tokio::test]
+async fn import_can_send_confirmation_emails() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "name,email\nUrsula,ursula@example.com\nTerry,terry@example.com";
+    let response = app.post_import_subscribers(csv, "send_confirmation").await;
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>2 imported, 0 skipped, 0 with errors.</p>"));
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_link(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let statuses = sqlx::query!("SELECT status FROM subscriptions ORDER BY status")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses[0].status, "confirmed");
+    assert_eq!(statuses[1].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn import_skips_existing_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, 'ursula@example.com', 'Ursula', now(), 'unsubscribed')",
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_import_subscribers("email,name\nursula@example.com,Ursula", "confirmed")
+        .await;
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>0 imported, 1 skipped, 0 with errors.</p>"));
+    assert!(html.contains("Already subscribed (unsubscribed)."));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn import_without_the_required_columns_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_import_subscribers("address,name\nursula@example.com,Ursula", "confirmed")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    let html = app
+        .api_client
+        .get(format!("{}/admin/subscribers/import", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p><i>The CSV file has no `email` column.</i></p>"));
+}
+
+#[tokio::test]
+async fn export_streams_every_subscriber_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // More rows than fit in a single chunk of the export.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'reader' || n || '@example.com', 'Reader, ' || n, now(), 'confirmed'
+        FROM generate_series(1, 1200) AS n
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.get_export_subscribers().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines[0], "email,name,status,subscribed_at");
+    assert_eq!(lines.len(), 1201);
+    assert!(body.contains("reader1200@example.com,\"Reader, 1200\",confirmed,"));
+}
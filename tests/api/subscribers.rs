@@ -0,0 +1,289 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    status: &str,
+    subscribed_at: DateTime<Utc>,
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, 'Reader', $3, $4)",
+        subscriber_id,
+        email,
+        subscribed_at,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "confirmed", Utc::now()).await;
+
+    let response = app.get_subscribers("").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_subscriber_details(&subscriber_id).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_name_and_status() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "ursula@example.com", "confirmed", Utc::now()).await;
+    insert_subscriber(&app, "ursa@example.com", "pending_confirmation", Utc::now()).await;
+    insert_subscriber(&app, "terry@example.com", "confirmed", Utc::now()).await;
+
+    let html = app.get_subscribers_html("email=urs").await;
+    assert!(html.contains("ursula@example.com"));
+    assert!(html.contains("ursa@example.com"));
+    assert!(!html.contains("terry@example.com"));
+
+    let html = app.get_subscribers_html("email=urs&status=confirmed").await;
+    assert!(html.contains("ursula@example.com"));
+    assert!(!html.contains("ursa@example.com"));
+
+    let html = app
+        .get_subscribers_html("name=reader&status=confirmed")
+        .await;
+    assert!(html.contains("<p>2 subscriber(s) found.</p>"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_subscription_date() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(
+        &app,
+        "early@example.com",
+        "confirmed",
+        Utc.ymd(2022, 1, 10).and_hms(12, 0, 0),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "middle@example.com",
+        "confirmed",
+        Utc.ymd(2022, 2, 28).and_hms(23, 59, 0),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "late@example.com",
+        "confirmed",
+        Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+    )
+    .await;
+
+    let html = app
+        .get_subscribers_html("subscribed_from=2022-02-01&subscribed_to=2022-02-28")
+        .await;
+
+    assert!(html.contains("middle@example.com"));
+    assert!(!html.contains("early@example.com"));
+    assert!(!html.contains("late@example.com"));
+}
+
+#[tokio::test]
+async fn an_invalid_date_is_rejected_with_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscribers("subscribed_from=yesterday").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..60 {
+        insert_subscriber(
+            &app,
+            &format!("reader{:02}@example.com", i),
+            "confirmed",
+            Utc::now() - chrono::Duration::minutes(i),
+        )
+        .await;
+    }
+
+    let html = app.get_subscribers_html("").await;
+    assert!(html.contains("<p>60 subscriber(s) found.</p>"));
+    assert!(html.contains("reader00@example.com"));
+    assert!(html.contains("reader49@example.com"));
+    assert!(!html.contains("reader50@example.com"));
+    assert!(html.contains("page=2\">Next</a>"));
+    assert!(!html.contains(">Previous</a>"));
+
+    let html = app.get_subscribers_html("page=2").await;
+    assert!(html.contains("reader50@example.com"));
+    assert!(html.contains("reader59@example.com"));
+    assert!(!html.contains("reader49@example.com"));
+    assert!(html.contains(">Previous</a>"));
+    assert!(!html.contains(">Next</a>"));
+}
+
+#[tokio::test]
+async fn out_of_range_pages_do_not_fail() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscribers("page=9223372036854775807").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscriber_details_show_the_delivery_history() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "confirmed", Utc::now()).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at) VALUES ($1, 'The first issue', 'text', '<p>html</p>', now())"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, outcome, n_attempts, first_attempted_at, last_attempted_at) VALUES ($1, 'ursula@example.com', 'sent', 2, now(), now())"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.get_subscriber_details(&subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+
+    assert!(html.contains("<h1>ursula@example.com</h1>"));
+    assert!(html.contains(&format!(
+        r#"<a href="/admin/newsletters/{}">The first issue</a>"#,
+        issue_id
+    )));
+    assert!(html.contains("<td>sent</td><td>2</td>"));
+}
+
+#[tokio::test]
+async fn unknown_subscriber_returns_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscriber_details(&Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_confirmed_and_unsubscribed_manually() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "pending_confirmation",
+        Utc::now(),
+    )
+    .await;
+    let details_path = format!("/admin/subscribers/{}", subscriber_id);
+
+    let response = app.post_subscriber_action(&subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, &details_path);
+    let html = app
+        .get_subscriber_details(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p><i>The subscriber has been confirmed.</i></p>"));
+    assert!(html.contains("<p>Status: confirmed</p>"));
+
+    let response = app
+        .post_subscriber_action(&subscriber_id, "unsubscribe")
+        .await;
+    assert_is_redirect_to(&response, &details_path);
+    let html = app
+        .get_subscriber_details(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p><i>The subscriber has been unsubscribed.</i></p>"));
+    assert!(html.contains("<p>Status: unsubscribed</p>"));
+
+    let response = app.post_subscriber_action(&subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, &details_path);
+    let html = app
+        .get_subscriber_details(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html
+        .contains("<p><i>The subscriber has unsubscribed, only they can subscribe again.</i></p>"));
+    assert!(html.contains("<p>Status: unsubscribed</p>"));
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_tokens() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "pending_confirmation",
+        Utc::now(),
+    )
+    .await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subsciption_tokens (subscription_token, subscriber_id, list_id)
+        SELECT 'token', $1, list_id FROM lists WHERE slug = 'newsletter'
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, 'pending_confirmation', now() FROM lists WHERE slug = 'newsletter'
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html = app.get_subscribers_html("").await;
+    assert!(html.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    assert!(!html.contains("ursula@example.com"));
+
+    let remaining = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subsciption_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 0);
+
+    let response = app.get_subscriber_details(&subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
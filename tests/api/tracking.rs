@@ -0,0 +1,207 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Publish an issue to a single confirmed subscriber and return its id and
+/// the HTML body of the email that went out.
+async fn send_issue(app: &TestApp, tracking: bool) -> (Uuid, String) {
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Read https://example.com/post",
+                "html": r#"<p><a href="https://example.com/post?a=1&amp;b=2">Read</a> <a href="{{ unsubscribe_url }}">Leave</a></p>"#,
+            },
+            "tracking": tracking,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let response: serde_json::Value = response.json().await.unwrap();
+    let issue_id = Uuid::parse_str(response["newsletter_issue_id"].as_str().unwrap()).unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    (issue_id, body[0]["HtmlBody"].as_str().unwrap().to_owned())
+}
+
+/// The tracking URLs of an email, pointed at the test server.
+fn tracking_links(app: &TestApp, html: &str, kind: &str) -> Vec<reqwest::Url> {
+    html.split('"')
+        .filter(|s| s.contains(&format!("/track/{}?", kind)))
+        .map(|s| {
+            let mut link = reqwest::Url::parse(&htmlescape::decode_html(s).unwrap()).unwrap();
+            link.set_port(Some(app.port)).unwrap();
+            link
+        })
+        .collect()
+}
+
+async fn count_events(app: &TestApp, issue_id: Uuid) -> i64 {
+    sqlx::query!(
+        r#"SELECT count(*) as "n!" FROM issue_events WHERE newsletter_issue_id = $1"#,
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n
+}
+
+#[tokio::test]
+async fn untracked_issues_are_sent_unchanged() {
+    let app = spawn_app().await;
+
+    let (_, html) = send_issue(&app, false).await;
+
+    assert!(html.contains(r#"<a href="https://example.com/post?a=1&amp;b=2">Read</a>"#));
+    assert!(!html.contains("/track/"));
+}
+
+#[tokio::test]
+async fn tracked_issues_rewrite_links_and_add_a_pixel() {
+    let app = spawn_app().await;
+
+    let (_, html) = send_issue(&app, true).await;
+
+    assert_eq!(tracking_links(&app, &html, "click").len(), 1);
+    assert_eq!(tracking_links(&app, &html, "open").len(), 1);
+    assert!(!html.contains(r#"href="https://example.com/post"#));
+    // The unsubscribe link goes straight to the unsubscribe page.
+    assert!(html.contains("/subscriptions/unsubscribe?subscriber_id="));
+}
+
+#[tokio::test]
+async fn clicks_are_recorded_and_redirected_to_the_original_link() {
+    let app = spawn_app().await;
+    let (issue_id, html) = send_issue(&app, true).await;
+    let link = tracking_links(&app, &html, "click").remove(0);
+
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/post?a=1&b=2"
+    );
+    let event = sqlx::query!("SELECT kind, url FROM issue_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "click");
+    assert_eq!(
+        event.url.as_deref(),
+        Some("https://example.com/post?a=1&b=2")
+    );
+    assert_eq!(count_events(&app, issue_id).await, 1);
+}
+
+#[tokio::test]
+async fn opens_are_recorded_by_the_pixel() {
+    let app = spawn_app().await;
+    let (issue_id, html) = send_issue(&app, true).await;
+    let pixel = tracking_links(&app, &html, "open").remove(0);
+
+    let response = app.api_client.get(pixel).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    assert_eq!(count_events(&app, issue_id).await, 1);
+}
+
+#[tokio::test]
+async fn forged_tracking_links_are_rejected() {
+    let app = spawn_app().await;
+    let (issue_id, html) = send_issue(&app, true).await;
+    let link = tracking_links(&app, &html, "click").remove(0);
+
+    let mut redirected = link.clone();
+    let query: Vec<(String, String)> = link
+        .query_pairs()
+        .map(|(k, v)| match &*k {
+            "url" => (k.into_owned(), "https://evil.example.com".to_owned()),
+            _ => (k.into_owned(), v.into_owned()),
+        })
+        .collect();
+    redirected.query_pairs_mut().clear().extend_pairs(query);
+    let mut forged_pixel = tracking_links(&app, &html, "open").remove(0);
+    forged_pixel.set_query(Some(&format!(
+        "issue_id={}&subscriber_id={}&tag=00",
+        issue_id,
+        Uuid::new_v4()
+    )));
+
+    for link in [redirected, forged_pixel] {
+        let response = app.api_client.get(link).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    assert_eq!(count_events(&app, issue_id).await, 0);
+}
+
+#[tokio::test]
+async fn the_status_page_summarizes_the_engagement() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (issue_id, html) = send_issue(&app, true).await;
+    let link = tracking_links(&app, &html, "click").remove(0);
+    let pixel = tracking_links(&app, &html, "open").remove(0);
+    for url in [pixel.clone(), pixel, link.clone(), link] {
+        app.api_client.get(url).send().await.unwrap();
+    }
+
+    let html = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains("Opens and clicks are tracked for this issue."));
+    assert!(html.contains("<li>Opened by: 1 (100.0%)</li>"));
+    assert!(html.contains("<li>Opens: 2</li>"));
+    assert!(html.contains("<li>Clicked by: 1 (100.0%)</li>"));
+    assert!(
+        html.contains("<tr><td>https://example.com/post?a=1&amp;b=2</td><td>1</td><td>2</td></tr>")
+    );
+}
+
+#[tokio::test]
+async fn nothing_is_recorded_once_tracking_is_turned_off() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (issue_id, html) = send_issue(&app, true).await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/tracking",
+            app.address, issue_id
+        ))
+        .form(&serde_json::json!({ "tracking": "false" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 303);
+    let status_html = app
+        .get_newsletter_issue_status(&issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(status_html.contains("Opens and clicks are no longer tracked for this issue."));
+
+    // Links keep working, but the click is not recorded.
+    let link = tracking_links(&app, &html, "click").remove(0);
+    let response = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(count_events(&app, issue_id).await, 0);
+}
@@ -0,0 +1,224 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use zero2prod::authentication::{current_totp_step, totp_code};
+
+/// Enable TOTP for the logged-in test user. Returns the secret and the
+/// recovery codes.
+async fn enable_totp(app: &TestApp) -> (Vec<u8>, Vec<String>) {
+    let resp = app.post_totp_code("/admin/security/totp", "").await;
+    assert_is_redirect_to(&resp, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("otpauth://totp/zero2prod%3A"));
+    let secret = between(&html_page, r#"<code id="totp-secret">"#, "</code>");
+    let secret = base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret).unwrap();
+
+    let resp = app
+        .post_totp_code("/admin/security/totp/confirm", &current_code(&secret))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let html_page = resp.text().await.unwrap();
+    let recovery_codes = between(&html_page, r#"<ul id="recovery-codes">"#, "</ul>")
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("<li><code>"))
+        .map(|line| line.trim_end_matches("</code></li>").to_owned())
+        .collect();
+    (secret, recovery_codes)
+}
+
+fn current_code(secret: &[u8]) -> String {
+    totp_code(secret, current_totp_step())
+}
+
+fn between<'a>(s: &'a str, start: &str, end: &str) -> &'a str {
+    let s = &s[s.find(start).unwrap() + start.len()..];
+    &s[..s.find(end).unwrap()]
+}
+
+async fn log_in_again(app: &TestApp) -> reqwest::Response {
+    assert_is_redirect_to(&app.post_logout().await, "/login");
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn totp_is_only_enabled_with_a_valid_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("Two-factor authentication is off."));
+
+    app.post_totp_code("/admin/security/totp", "").await;
+    let resp = app
+        .post_totp_code("/admin/security/totp/confirm", "not-a-code")
+        .await;
+    assert_is_redirect_to(&resp, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>The code is incorrect.</i></p>"));
+    assert!(html_page.contains("<svg"));
+
+    let (_, recovery_codes) = enable_totp(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("Two-factor authentication is on. You have 10 recovery codes left."));
+
+    // The secret is not stored in clear.
+    let row = sqlx::query!(
+        r#"SELECT totp_secret AS "totp_secret!" FROM users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_ne!(row.totp_secret.len(), 20);
+}
+
+#[tokio::test]
+async fn login_requires_a_code_once_totp_is_enabled() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enable_totp(&app).await;
+
+    let resp = log_in_again(&app).await;
+    assert_is_redirect_to(&resp, "/login/two_factor");
+    // The password alone does not log in.
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+
+    let resp = app
+        .post_totp_code("/login/two_factor", &current_code(&secret))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let resp = app.get_admin_dashboard().await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_in_again_without_a_code_ends_the_current_login() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enable_totp(&app).await;
+
+    // Still logged in from before TOTP was enabled.
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/login/two_factor");
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enable_totp(&app).await;
+    let code = current_code(&secret);
+
+    log_in_again(&app).await;
+    let resp = app.post_totp_code("/login/two_factor", &code).await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+
+    log_in_again(&app).await;
+    let resp = app.post_totp_code("/login/two_factor", &code).await;
+    assert_is_redirect_to(&resp, "/login/two_factor");
+    let html_page = app
+        .api_client
+        .get(format!("{}/login/two_factor", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The code is incorrect.</i></p>"));
+}
+
+#[tokio::test]
+async fn recovery_codes_can_be_used_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enable_totp(&app).await;
+
+    log_in_again(&app).await;
+    let resp = app
+        .post_totp_code("/login/two_factor", &recovery_codes[0].to_uppercase())
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("You have 9 recovery codes left."));
+
+    log_in_again(&app).await;
+    let resp = app
+        .post_totp_code("/login/two_factor", &recovery_codes[0])
+        .await;
+    assert_is_redirect_to(&resp, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_lock_the_user_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enable_totp(&app).await;
+    log_in_again(&app).await;
+
+    for _ in 0..4 {
+        let resp = app.post_totp_code("/login/two_factor", "not-a-code").await;
+        assert_is_redirect_to(&resp, "/login/two_factor");
+    }
+    let resp = app.post_totp_code("/login/two_factor", "not-a-code").await;
+    assert_is_redirect_to(&resp, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Too many wrong codes, please try again later.</i></p>"));
+
+    let resp = app
+        .api_client
+        .get(format!("{}/login/two_factor", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&resp, "/login");
+
+    // Entering the password again does not give more tries.
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/login/two_factor");
+    let resp = app
+        .post_totp_code("/login/two_factor", &current_code(&secret))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+}
+
+#[tokio::test]
+async fn totp_can_be_turned_off_with_a_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enable_totp(&app).await;
+
+    let resp = app
+        .post_totp_code("/admin/security/totp/disable", "not-a-code")
+        .await;
+    assert_is_redirect_to(&resp, "/admin/security");
+    assert!(app
+        .get_security_html()
+        .await
+        .contains("<p><i>The code is incorrect.</i></p>"));
+
+    let resp = app
+        .post_totp_code("/admin/security/totp/disable", &current_code(&secret))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>Two-factor authentication is off.</i></p>"));
+
+    let resp = log_in_again(&app).await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+}
@@ -0,0 +1,167 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    app.insert_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await
+}
+
+async fn enqueue_issue(app: &TestApp) {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at) VALUES ($1, 'Newsletter title', 'Newsletter body as plain text', '<p>Newsletter body as HTML</p>', now())"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.target_default_list(issue_id).await;
+    sqlx::query!(
+        r#"INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) SELECT $1, email FROM subscriptions WHERE status = 'confirmed'"#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn deliver_issue_and_get_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    enqueue_issue(app).await;
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+async fn get_status(app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn newsletter_emails_advertise_one_click_unsubscription() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    enqueue_issue(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let headers = body[0]["Headers"].as_array().unwrap();
+    assert!(headers.iter().any(
+        |h| h["Name"] == "List-Unsubscribe-Post" && h["Value"] == "List-Unsubscribe=One-Click"
+    ));
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+}
+
+#[tokio::test]
+async fn unsubscribe_link_shows_a_confirmation_page() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let resp = reqwest::get(unsubscribe_link).await.unwrap();
+
+    assert_eq!(resp.status().as_u16(), 200);
+    assert!(resp
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"<button type="submit">Unsubscribe</button>"#));
+    assert_eq!(get_status(&app, subscriber_id).await, "confirmed");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_issue_and_get_unsubscribe_link(&app).await;
+
+    let resp = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(get_status(&app, subscriber_id).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribe_with_a_forged_tag_is_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+
+    let resp = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?subscriber_id={}&tag={}",
+            app.address,
+            subscriber_id,
+            hex::encode([0u8; 32])
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status().as_u16(), 401);
+    assert_eq!(get_status(&app, subscriber_id).await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_after_an_issue_is_enqueued_are_skipped() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    enqueue_issue(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 0);
+}
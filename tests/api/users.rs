@@ -0,0 +1,221 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::create_api_token;
+
+/// Invite `email` as the logged-in test user and return the invite link.
+async fn invite(app: &TestApp, email: &str, role: &str) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app.post_invite_user(email, role).await;
+    assert_is_redirect_to(&resp, "/admin/users");
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    app.get_confirmation_link(requests.last().unwrap()).html
+}
+
+async fn accept_invite(app: &TestApp, link: &reqwest::Url, username: &str) -> reqwest::Response {
+    app.api_client
+        .post(link.clone())
+        .form(&serde_json::json!({
+            "username": username,
+            "password": "a-new-password",
+            "password_check": "a-new-password",
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn store_other_user(app: &TestApp) -> TestUser {
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    user
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let app = spawn_app().await;
+    app.set_test_user_role("editor").await;
+    app.test_user.login(&app).await;
+
+    let resp = app
+        .api_client
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let resp = app.post_invite_user("le_guin@example.com", "owner").await;
+    assert_eq!(resp.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_invited_user_can_create_an_account_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let link = invite(&app, "le_guin@example.com", "editor").await;
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>An invite has been sent to le_guin@example.com.</i></p>"));
+    assert!(html_page.contains("<td>le_guin@example.com</td><td>editor</td>"));
+
+    let resp = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    assert!(resp
+        .text()
+        .await
+        .unwrap()
+        .contains("invited with the editor role"));
+
+    let resp = accept_invite(&app, &link, "ursula").await;
+    assert_is_redirect_to(&resp, "/login");
+
+    let user = sqlx::query!("SELECT role FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.role, "editor");
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+
+    // The link cannot be used a second time
+    let resp = accept_invite(&app, &link, "someone-else").await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn invite_links_must_be_signed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut link = invite(&app, "le_guin@example.com", "owner").await;
+
+    let forged_query = link.query().unwrap().replace("tag=", "tag=00");
+    link.set_query(Some(&forged_query));
+    let resp = accept_invite(&app, &link, "ursula").await;
+
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn disabled_users_cannot_log_in() {
+    let app = spawn_app().await;
+    let other_user = store_other_user(&app).await;
+    app.test_user.login(&app).await;
+
+    let resp = app.post_user_action(&other_user.user_id, "disable").await;
+    assert_is_redirect_to(&resp, "/admin/users");
+    app.post_logout().await;
+
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &other_user.username,
+            "password": &other_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+
+    app.test_user.login(&app).await;
+    app.post_user_action(&other_user.user_id, "enable").await;
+    app.post_logout().await;
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &other_user.username,
+            "password": &other_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn disabling_a_user_ends_their_session_and_rejects_their_tokens() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    sqlx::query!(
+        "UPDATE users SET disabled_at = now() WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+    let resp = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn owners_cannot_disable_or_delete_themselves() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_user_action(&app.test_user.user_id, "disable")
+        .await;
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>You cannot disable your own account.</i></p>"));
+
+    app.post_user_action(&app.test_user.user_id, "delete").await;
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>You cannot delete your own account.</i></p>"));
+}
+
+#[tokio::test]
+async fn deleting_a_user_removes_their_tokens_and_idempotency_keys() {
+    let app = spawn_app().await;
+    let other_user = store_other_user(&app).await;
+    let token = create_api_token(&app.db_pool, other_user.user_id, "ci")
+        .await
+        .unwrap();
+    let resp = app
+        .post_api_newsletters(
+            secrecy::ExposeSecret::expose_secret(&token),
+            &Uuid::new_v4().to_string(),
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "content": { "text": "Text", "html": "<p>HTML</p>" }
+            }),
+        )
+        .await;
+    assert_eq!(resp.status().as_u16(), 202);
+    app.test_user.login(&app).await;
+
+    let resp = app.post_user_action(&other_user.user_id, "delete").await;
+    assert_is_redirect_to(&resp, "/admin/users");
+
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>The user has been deleted.</i></p>"));
+    assert!(!html_page.contains(&other_user.username));
+    let n_keys = sqlx::query!(
+        r#"SELECT count(*) AS "n!" FROM idempotency WHERE user_id = $1"#,
+        other_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_keys, 0);
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 1);
+}
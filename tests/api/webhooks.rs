@@ -0,0 +1,262 @@
+use crate::helper::{spawn_app, PostmarkBatchResponder, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807u64,
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Email": email,
+        "BouncedAt": "2022-07-15T10:00:00Z",
+        "Description": "The server was unable to deliver your message.",
+        "Inactive": true
+    })
+}
+
+async fn subscriber_status(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn count_email_events(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "n!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn webhooks_without_the_shared_secret_are_rejected() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    let response = app
+        .post_postmark_webhook("wrong-secret", &hard_bounce("ursula@example.com"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .json(&hard_bounce("ursula@example.com"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(count_email_events(&app).await, 0);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn invalid_payloads_are_rejected() {
+    let app = spawn_app().await;
+
+    for body in [
+        serde_json::json!({ "Email": "ursula@example.com" }),
+        serde_json::json!({ "RecordType": "Bounce", "Type": "HardBounce" }),
+        serde_json::json!({ "RecordType": "Delivery", "Email": "ursula@example.com" }),
+    ] {
+        let response = app.post_postmark_webhook(&app.webhook_secret, &body).await;
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", body);
+    }
+}
+
+#[tokio::test]
+async fn a_hard_bounce_suppresses_the_address() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    let response = app
+        .post_postmark_webhook(&app.webhook_secret, &hard_bounce("Ursula@Example.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "suppressed"
+    );
+    let event = sqlx::query!("SELECT record_type, email, message_id, details FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.email, "ursula@example.com");
+    assert_eq!(
+        event.message_id.as_deref(),
+        Some("883953f4-6105-42a2-a16a-77a8eac79483")
+    );
+    assert_eq!(event.details.as_deref(), Some("HardBounce"));
+}
+
+#[tokio::test]
+async fn a_spam_complaint_suppresses_the_address() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    let response = app
+        .post_postmark_webhook(
+            &app.webhook_secret,
+            &serde_json::json!({
+                "RecordType": "SpamComplaint",
+                "MessageID": "00000000-0000-0000-0000-000000000000",
+                "Type": "SpamComplaint",
+                "Email": "ursula@example.com",
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "suppressed"
+    );
+}
+
+#[tokio::test]
+async fn soft_bounces_and_deliveries_are_recorded_without_suppressing() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+
+    for body in [
+        serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "Email": "ursula@example.com",
+        }),
+        serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "00000000-0000-0000-0000-000000000000",
+            "Recipient": "ursula@example.com",
+            "DeliveredAt": "2022-07-15T10:00:00Z",
+        }),
+    ] {
+        let response = app.post_postmark_webhook(&app.webhook_secret, &body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(count_email_events(&app).await, 2);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn other_record_types_are_acknowledged_and_ignored() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(
+            &app.webhook_secret,
+            &serde_json::json!({ "RecordType": "Open", "Recipient": "ursula@example.com" }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_email_events(&app).await, 0);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_sent_new_issues() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    app.insert_confirmed_subscriber("terry@example.com").await;
+    app.post_postmark_webhook(&app.webhook_secret, &hard_bounce("ursula@example.com"))
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let recipients: Vec<_> = body.as_array().unwrap().iter().map(|e| &e["To"]).collect();
+    assert_eq!(recipients, ["terry@example.com"]);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_get_no_confirmation_email_when_subscribing_again() {
+    let app = spawn_app().await;
+    app.insert_confirmed_subscriber("ursula@example.com").await;
+    app.post_postmark_webhook(&app.webhook_secret, &hard_bounce("ursula@example.com"))
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "suppressed"
+    );
+}
+
+#[tokio::test]
+async fn confirming_again_does_not_lift_a_suppression() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=Ursula%40Example.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_link(email_request);
+    app.post_postmark_webhook(&app.webhook_secret, &hard_bounce("URSULA@example.com"))
+        .await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "suppressed"
+    );
+
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.post_subscriber_action(&subscriber_id, "confirm").await;
+    assert_eq!(
+        subscriber_status(&app, "ursula@example.com").await,
+        "suppressed"
+    );
+}